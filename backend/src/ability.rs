@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+type Address = usize;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Ability {
+    Beam,
+    Bomb,
+    Burning,
+    Crash,
+    Cupid,
+    Cutter,
+    Fighter,
+    Hammer,
+    Ice,
+    Laser,
+    Magic,
+    Mini,
+    Missile,
+    Parasol,
+    Sleep,
+    Smash,
+    Spark,
+    Stone,
+    Sword,
+    Throw,
+    Tornado,
+    Ufo,
+    Wheel,
+}
+
+impl Ability {
+    pub const ALL: [Ability; 23] = [
+        Ability::Beam,
+        Ability::Bomb,
+        Ability::Burning,
+        Ability::Crash,
+        Ability::Cupid,
+        Ability::Cutter,
+        Ability::Fighter,
+        Ability::Hammer,
+        Ability::Ice,
+        Ability::Laser,
+        Ability::Magic,
+        Ability::Mini,
+        Ability::Missile,
+        Ability::Parasol,
+        Ability::Sleep,
+        Ability::Smash,
+        Ability::Spark,
+        Ability::Stone,
+        Ability::Sword,
+        Ability::Throw,
+        Ability::Tornado,
+        Ability::Ufo,
+        Ability::Wheel,
+    ];
+
+    pub fn tier(self) -> AbilityTier {
+        match self {
+            Ability::Crash | Ability::Magic | Ability::Smash | Ability::Ufo => AbilityTier::Rare,
+            _ => AbilityTier::Common,
+        }
+    }
+}
+
+// Rare abilities are only given by a handful of enemies and pedestals, and are much stronger than
+// the common ones most enemies give
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AbilityTier {
+    Common,
+    Rare,
+}
+
+// An enemy or pedestal that gives Kirby a copy ability
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AbilitySource<IDType> {
+    // The node Kirby can get the ability at
+    pub node: IDType,
+    pub ability: Ability,
+    // Where the ability of the source is stored in the ROM
+    #[serde(default)]
+    pub addresses: Vec<Address>,
+}
+
+// Abilities Kirby needs to pass through an edge, e.g. Or([Ability(Cutter), Ability(Sword)])
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum Requirement {
+    Ability(Ability),
+    And(Vec<Requirement>),
+    Or(Vec<Requirement>),
+}
+
+impl Requirement {
+    pub fn is_met(&self, abilities: &HashSet<Ability>) -> bool {
+        match self {
+            Requirement::Ability(ability) => abilities.contains(ability),
+            Requirement::And(requirements) => requirements.iter().all(|r| r.is_met(abilities)),
+            Requirement::Or(requirements) => requirements.iter().any(|r| r.is_met(abilities)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requirement_is_met() {
+        let abilities: HashSet<Ability> =
+            vec![Ability::Cutter, Ability::Bomb].into_iter().collect();
+
+        assert!(Requirement::Ability(Ability::Cutter).is_met(&abilities));
+        assert!(!Requirement::Ability(Ability::Hammer).is_met(&abilities));
+        assert!(Requirement::And(vec![
+            Requirement::Ability(Ability::Cutter),
+            Requirement::Ability(Ability::Bomb)
+        ])
+        .is_met(&abilities));
+        assert!(!Requirement::And(vec![
+            Requirement::Ability(Ability::Cutter),
+            Requirement::Ability(Ability::Hammer)
+        ])
+        .is_met(&abilities));
+        assert!(Requirement::Or(vec![
+            Requirement::Ability(Ability::Sword),
+            Requirement::Ability(Ability::Cutter)
+        ])
+        .is_met(&abilities));
+        assert!(!Requirement::Or(vec![]).is_met(&abilities));
+    }
+}
@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum BossKind {
+    // Drops one of the mirror shards, so every boss has to be beaten
+    Boss,
+    // Only guards a door
+    MiniBoss,
+}
+
+// A boss and the room it is fought in
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Boss<IDType> {
+    pub name: String,
+    pub kind: BossKind,
+    // The node Kirby enters the fight at
+    pub entrance: IDType,
+}
+
+// Doors leading into a boss fight. Bosses only replace bosses of the same kind.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BossRoom<IDType> {
+    // Every door has a one-way edge to the entrance of the boss
+    pub doors: Vec<IDType>,
+    // The boss behind the doors, vanilla until the bosses are shuffled
+    pub boss: Boss<IDType>,
+}
@@ -1,18 +1,625 @@
+use crate::palette::{KirbyColor, Rgb, SprayPaint};
 use rocket::form::FromFormField;
+use serde::{Deserialize, Serialize};
+use std::string::FromUtf8Error;
+use thiserror::Error;
 
-#[derive(Debug)]
-pub struct EntranceData;
+// Bump this whenever the settings string layout changes, and keep decoding the older layouts
+const SETTINGS_VERSION: u8 = 8;
 
-#[derive(Copy, Clone, Debug, FromFormField)]
+// Gives each variant of a setting the code it is stored as in settings strings. These codes must
+// never change, or old settings strings break.
+macro_rules! settings_codes {
+    ($type:ident { $($variant:ident => $code:literal),* $(,)? }) => {
+        impl $type {
+            fn code(self) -> u8 {
+                match self {
+                    $($type::$variant => $code,)*
+                }
+            }
+
+            fn from_code(code: u8) -> Option<Self> {
+                match code {
+                    $($code => Some($type::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, FromFormField, Serialize)]
 pub enum EntranceShuffleType {
     // two-way doors are truly two-way; one-way doors lead to one-way exits
     Standard,
     // two-way doors behave like one-way doors; every door can lead to any exit
     Chaos,
+    // like standard, but doors only lead to doors of their own area
+    AreaRestricted,
+    // only doors between two areas are shuffled; the layout inside each area stays vanilla
+    AreaConnections,
+}
+
+settings_codes!(EntranceShuffleType {
+    Standard => 0,
+    Chaos => 1,
+    AreaRestricted => 2,
+    AreaConnections => 3,
+});
+
+#[derive(Copy, Clone, Debug, PartialEq, FromFormField, Serialize)]
+pub enum AbilityShuffleType {
+    // every enemy and pedestal gives its vanilla ability
+    Off,
+    // abilities are only replaced by abilities of the same tier
+    SameTier,
+    // any ability can be replaced by any other
+    Full,
+}
+
+settings_codes!(AbilityShuffleType {
+    Off => 0,
+    SameTier => 1,
+    Full => 2,
+});
+
+#[derive(Copy, Clone, Debug, PartialEq, FromFormField, Serialize)]
+pub enum EnemyShuffleType {
+    // every room keeps its vanilla enemies
+    Off,
+    // enemies Kirby can get a copy ability from keep their place, so the ability logic holds
+    KeepAbilitySources,
+    // every enemy can be replaced by any enemy of the same class
+    Full,
+}
+
+settings_codes!(EnemyShuffleType {
+    Off => 0,
+    KeepAbilitySources => 1,
+    Full => 2,
+});
+
+#[derive(Copy, Clone, Debug, PartialEq, FromFormField, Serialize)]
+pub enum MusicShuffleType {
+    // every area and room plays its vanilla music
+    Off,
+    // background music is shuffled, while jingles stay where they are
+    ExcludeJingles,
+    // background music and jingles are shuffled together
+    Full,
+}
+
+settings_codes!(MusicShuffleType {
+    Off => 0,
+    ExcludeJingles => 1,
+    Full => 2,
+});
+
+#[derive(Error, Debug)]
+pub enum PlandoParseError {
+    #[error("Invalid RON plando: {0}")]
+    Ron(#[from] ron::Error),
+    #[error("Invalid JSON plando: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum SettingsStringError {
+    #[error("Settings string is not valid URL-safe base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("Settings string is empty")]
+    Empty,
+    #[error(
+        "Settings string version {0} is not supported; this randomizer reads versions 1 to {}",
+        SETTINGS_VERSION
+    )]
+    UnsupportedVersion(u8),
+    #[error("Settings string ends unexpectedly")]
+    UnexpectedEnd,
+    #[error("Settings string has {0} unexpected byte(s) at the end")]
+    TrailingBytes(usize),
+    #[error("Settings string has an unknown entrance shuffle type {0}")]
+    UnknownShuffleType(u8),
+    #[error("Settings string has an unknown ability shuffle type {0}")]
+    UnknownAbilityShuffleType(u8),
+    #[error("Settings string has an unknown enemy shuffle type {0}")]
+    UnknownEnemyShuffleType(u8),
+    #[error("Settings string has an unknown music shuffle type {0}")]
+    UnknownMusicShuffleType(u8),
+    #[error("Settings string has an unknown Kirby color type {0}")]
+    UnknownKirbyColorType(u8),
+    #[error("Settings string has an unknown spray paint {0}")]
+    UnknownSprayPaint(u8),
+    #[error("Settings string has an invalid on/off setting {0}")]
+    InvalidFlag(u8),
+    #[error("Settings string has a number that is too large")]
+    NumberTooLarge,
+    #[error("Settings string has invalid text: {0}")]
+    InvalidText(#[from] FromUtf8Error),
+}
+
+// A door that should always lead to a given entrance. Both are node IDs from the game data, e.g.
+// "PP: Box Boxer door in miniboss central".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlandoConnection {
+    pub door: String,
+    pub entrance: String,
+}
+
+// Connections picked by the user. These are placed before shuffling and the shuffle works around them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Plando {
+    #[serde(default)]
+    pub connections: Vec<PlandoConnection>,
+}
+
+impl Plando {
+    // Plandos can be written in either RON or JSON. JSON plandos are objects, so they always start
+    // with a brace, which RON structs never do.
+    pub fn parse(contents: &str) -> Result<Self, PlandoParseError> {
+        if contents.trim_start().starts_with('{') {
+            Ok(serde_json::from_str(contents)?)
+        } else {
+            Ok(ron::from_str(contents)?)
+        }
+    }
 }
 
 // Represents a user's input configuration
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Config {
     pub seed: u64,
     pub entrance_shuffle: EntranceShuffleType,
+    pub plando: Plando,
+    // Doors that keep their vanilla entrance in this seed
+    pub excluded_doors: Vec<String>,
+    // Race seeds mix a secret salt into the RNG seed, so the layout can only be generated by the
+    // server and the spoiler stays hidden until the race is unlocked
+    pub race: bool,
+    // Shuffles the items in treasure chests
+    pub shuffle_chests: bool,
+    pub ability_shuffle: AbilityShuffleType,
+    pub enemy_shuffle: EnemyShuffleType,
+    // Shuffles which boss or mini-boss is behind each boss door
+    pub shuffle_bosses: bool,
+    // Cosmetic only, so it never changes the logic or the seed hash
+    pub music_shuffle: MusicShuffleType,
+    // Colors of Kirby and the helpers, in the order of `Kirby::ALL`. Cosmetic only, like the music.
+    pub kirby_colors: [KirbyColor; 4],
+}
+
+// A standard shuffle with every other option turned off. Only tests build configs this way, since
+// real configs always come from the form or a settings string.
+#[cfg(test)]
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            seed: 0,
+            entrance_shuffle: EntranceShuffleType::Standard,
+            plando: Plando::default(),
+            excluded_doors: vec![],
+            race: false,
+            shuffle_chests: false,
+            ability_shuffle: AbilityShuffleType::Off,
+            enemy_shuffle: EnemyShuffleType::Off,
+            shuffle_bosses: false,
+            music_shuffle: MusicShuffleType::Off,
+            kirby_colors: [KirbyColor::Vanilla; 4],
+        }
+    }
+}
+
+// Settings strings are a version byte followed by the settings, encoded as URL-safe base64.
+// Numbers are LEB128 varints and text is a varint length followed by UTF-8 bytes.
+impl Config {
+    pub fn to_settings_string(&self) -> String {
+        let mut bytes = vec![SETTINGS_VERSION];
+        write_varint(&mut bytes, self.seed);
+        bytes.push(self.entrance_shuffle.code());
+        write_varint(&mut bytes, self.plando.connections.len() as u64);
+        for connection in &self.plando.connections {
+            write_string(&mut bytes, &connection.door);
+            write_string(&mut bytes, &connection.entrance);
+        }
+        write_varint(&mut bytes, self.excluded_doors.len() as u64);
+        for door in &self.excluded_doors {
+            write_string(&mut bytes, door);
+        }
+        bytes.push(self.race as u8);
+        bytes.push(self.shuffle_chests as u8);
+        bytes.push(self.ability_shuffle.code());
+        bytes.push(self.enemy_shuffle.code());
+        bytes.push(self.shuffle_bosses as u8);
+        bytes.push(self.music_shuffle.code());
+        for color in &self.kirby_colors {
+            write_kirby_color(&mut bytes, *color);
+        }
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn from_settings_string(settings: &str) -> Result<Self, SettingsStringError> {
+        let bytes = base64::decode_config(settings.trim(), base64::URL_SAFE_NO_PAD)?;
+        let mut reader = SettingsReader { bytes: &bytes };
+        let config = match reader.byte().map_err(|_| SettingsStringError::Empty)? {
+            1 => reader.config_v1()?,
+            2 => reader.config_v2()?,
+            3 => reader.config_v3()?,
+            4 => reader.config_v4()?,
+            5 => reader.config_v5()?,
+            6 => reader.config_v6()?,
+            7 => reader.config_v7()?,
+            8 => reader.config_v8()?,
+            version => return Err(SettingsStringError::UnsupportedVersion(version)),
+        };
+        match reader.bytes.len() {
+            0 => Ok(config),
+            trailing => Err(SettingsStringError::TrailingBytes(trailing)),
+        }
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+// A code for the kind of color, followed by the spray paint code or the RGB channels
+fn write_kirby_color(bytes: &mut Vec<u8>, color: KirbyColor) {
+    match color {
+        KirbyColor::Vanilla => bytes.push(0),
+        KirbyColor::Random => bytes.push(1),
+        KirbyColor::Preset(paint) => bytes.extend_from_slice(&[2, paint.code()]),
+        KirbyColor::Custom(Rgb { r, g, b }) => bytes.extend_from_slice(&[3, r, g, b]),
+    }
+}
+
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    write_varint(bytes, value.len() as u64);
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+struct SettingsReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl SettingsReader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], SettingsStringError> {
+        if len > self.bytes.len() {
+            return Err(SettingsStringError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, SettingsStringError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, SettingsStringError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7F);
+            if bits << shift >> shift != bits {
+                return Err(SettingsStringError::NumberTooLarge);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SettingsStringError::NumberTooLarge)
+    }
+
+    fn flag(&mut self) -> Result<bool, SettingsStringError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            flag => Err(SettingsStringError::InvalidFlag(flag)),
+        }
+    }
+
+    fn len(&mut self) -> Result<usize, SettingsStringError> {
+        let len = self.varint()?;
+        // Every item takes at least a byte, so a longer length can't be right
+        if len > self.bytes.len() as u64 {
+            return Err(SettingsStringError::UnexpectedEnd);
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, SettingsStringError> {
+        let len = self.len()?;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    fn kirby_color(&mut self) -> Result<KirbyColor, SettingsStringError> {
+        match self.byte()? {
+            0 => Ok(KirbyColor::Vanilla),
+            1 => Ok(KirbyColor::Random),
+            2 => {
+                let code = self.byte()?;
+                SprayPaint::from_code(code)
+                    .map(KirbyColor::Preset)
+                    .ok_or(SettingsStringError::UnknownSprayPaint(code))
+            }
+            3 => Ok(KirbyColor::Custom(Rgb::new(
+                self.byte()?,
+                self.byte()?,
+                self.byte()?,
+            ))),
+            code => Err(SettingsStringError::UnknownKirbyColorType(code)),
+        }
+    }
+
+    fn config_v1(&mut self) -> Result<Config, SettingsStringError> {
+        let seed = self.varint()?;
+        let code = self.byte()?;
+        let entrance_shuffle = EntranceShuffleType::from_code(code)
+            .ok_or(SettingsStringError::UnknownShuffleType(code))?;
+        let connections = (0..self.len()?)
+            .map(|_| {
+                Ok(PlandoConnection {
+                    door: self.string()?,
+                    entrance: self.string()?,
+                })
+            })
+            .collect::<Result<Vec<PlandoConnection>, SettingsStringError>>()?;
+        let excluded_doors = (0..self.len()?)
+            .map(|_| self.string())
+            .collect::<Result<Vec<String>, SettingsStringError>>()?;
+        Ok(Config {
+            seed,
+            entrance_shuffle,
+            plando: Plando { connections },
+            excluded_doors,
+            race: false,
+            shuffle_chests: false,
+            ability_shuffle: AbilityShuffleType::Off,
+            enemy_shuffle: EnemyShuffleType::Off,
+            shuffle_bosses: false,
+            music_shuffle: MusicShuffleType::Off,
+            kirby_colors: [KirbyColor::Vanilla; 4],
+        })
+    }
+
+    // Version 2 added race mode
+    fn config_v2(&mut self) -> Result<Config, SettingsStringError> {
+        let config = self.config_v1()?;
+        let race = self.flag()?;
+        Ok(Config { race, ..config })
+    }
+
+    // Version 3 added chest shuffle
+    fn config_v3(&mut self) -> Result<Config, SettingsStringError> {
+        let config = self.config_v2()?;
+        let shuffle_chests = self.flag()?;
+        Ok(Config {
+            shuffle_chests,
+            ..config
+        })
+    }
+
+    // Version 4 added ability shuffle
+    fn config_v4(&mut self) -> Result<Config, SettingsStringError> {
+        let config = self.config_v3()?;
+        let code = self.byte()?;
+        let ability_shuffle = AbilityShuffleType::from_code(code)
+            .ok_or(SettingsStringError::UnknownAbilityShuffleType(code))?;
+        Ok(Config {
+            ability_shuffle,
+            ..config
+        })
+    }
+
+    // Version 5 added enemy shuffle
+    fn config_v5(&mut self) -> Result<Config, SettingsStringError> {
+        let config = self.config_v4()?;
+        let code = self.byte()?;
+        let enemy_shuffle = EnemyShuffleType::from_code(code)
+            .ok_or(SettingsStringError::UnknownEnemyShuffleType(code))?;
+        Ok(Config {
+            enemy_shuffle,
+            ..config
+        })
+    }
+
+    // Version 6 added boss shuffle
+    fn config_v6(&mut self) -> Result<Config, SettingsStringError> {
+        let config = self.config_v5()?;
+        let shuffle_bosses = self.flag()?;
+        Ok(Config {
+            shuffle_bosses,
+            ..config
+        })
+    }
+
+    // Version 7 added music shuffle
+    fn config_v7(&mut self) -> Result<Config, SettingsStringError> {
+        let config = self.config_v6()?;
+        let code = self.byte()?;
+        let music_shuffle = MusicShuffleType::from_code(code)
+            .ok_or(SettingsStringError::UnknownMusicShuffleType(code))?;
+        Ok(Config {
+            music_shuffle,
+            ..config
+        })
+    }
+
+    // Version 8 added Kirby colors
+    fn config_v8(&mut self) -> Result<Config, SettingsStringError> {
+        let config = self.config_v7()?;
+        let mut kirby_colors = [KirbyColor::Vanilla; 4];
+        for color in kirby_colors.iter_mut() {
+            *color = self.kirby_color()?;
+        }
+        Ok(Config {
+            kirby_colors,
+            ..config
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(door: &str, entrance: &str) -> PlandoConnection {
+        PlandoConnection {
+            door: door.to_string(),
+            entrance: entrance.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_plando() -> Result<(), PlandoParseError> {
+        let expected = Plando {
+            connections: vec![connection("a", "b"), connection("c", "d")],
+        };
+        let ron_plando = Plando::parse(
+            r#"(connections: [(door: "a", entrance: "b"), (door: "c", entrance: "d")])"#,
+        )?;
+        let json_plando = Plando::parse(
+            r#"{"connections": [{"door": "a", "entrance": "b"}, {"door": "c", "entrance": "d"}]}"#,
+        )?;
+        assert_eq!(ron_plando, expected);
+        assert_eq!(json_plando, expected);
+        assert!(Plando::parse("()")?.connections.is_empty());
+        Ok(())
+    }
+
+    fn test_config() -> Config {
+        Config {
+            seed: 1234567890,
+            entrance_shuffle: EntranceShuffleType::AreaRestricted,
+            plando: Plando {
+                connections: vec![connection("PP: Door a", "RRoute: Entrance b")],
+            },
+            excluded_doors: vec!["CCav: Door c".to_string()],
+            race: true,
+            shuffle_chests: true,
+            ability_shuffle: AbilityShuffleType::Full,
+            enemy_shuffle: EnemyShuffleType::KeepAbilitySources,
+            shuffle_bosses: true,
+            music_shuffle: MusicShuffleType::ExcludeJingles,
+            kirby_colors: [
+                KirbyColor::Vanilla,
+                KirbyColor::Random,
+                KirbyColor::Preset(SprayPaint::Carbon),
+                KirbyColor::Custom(Rgb::new(0xFF, 0x88, 0x00)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_settings_string_round_trip() -> Result<(), SettingsStringError> {
+        let config = test_config();
+        let settings = config.to_settings_string();
+        assert!(settings
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(Config::from_settings_string(&settings)?, config);
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_string_versions() -> Result<(), SettingsStringError> {
+        let config = Config {
+            seed: 42,
+            entrance_shuffle: EntranceShuffleType::Chaos,
+            ..Config::default()
+        };
+        assert_eq!(config.to_settings_string(), "CCoBAAAAAAAAAAAAAAAA");
+        assert_eq!(
+            Config::from_settings_string("CCoBAAAAAAAAAAAAAAAA")?,
+            config
+        );
+        // Strings shared before a format change must keep decoding to the same settings
+        assert_eq!(Config::from_settings_string("ASoBAAA")?, config);
+        assert_eq!(Config::from_settings_string("AioBAAAA")?, config);
+        assert_eq!(Config::from_settings_string("AyoBAAAAAA")?, config);
+        assert_eq!(Config::from_settings_string("BCoBAAAAAAA")?, config);
+        assert_eq!(Config::from_settings_string("BSoBAAAAAAAA")?, config);
+        assert_eq!(Config::from_settings_string("BioBAAAAAAAAAA")?, config);
+        assert_eq!(Config::from_settings_string("ByoBAAAAAAAAAAA")?, config);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_settings_string() {
+        let encode = |bytes: &[u8]| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+        assert!(matches!(
+            Config::from_settings_string("not base64!"),
+            Err(SettingsStringError::Base64(_))
+        ));
+        assert!(matches!(
+            Config::from_settings_string(""),
+            Err(SettingsStringError::Empty)
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[SETTINGS_VERSION + 1, 42, 1, 0, 0, 0, 0, 0])),
+            Err(SettingsStringError::UnsupportedVersion(v)) if v == SETTINGS_VERSION + 1
+        ));
+        let bytes =
+            base64::decode_config(test_config().to_settings_string(), base64::URL_SAFE_NO_PAD)
+                .expect("Settings strings are valid base64");
+        assert!(matches!(
+            Config::from_settings_string(&encode(&bytes[..bytes.len() - 3])),
+            Err(SettingsStringError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[1, 42, 1, 0, 0, 0])),
+            Err(SettingsStringError::TrailingBytes(1))
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[1, 42, 9, 0, 0])),
+            Err(SettingsStringError::UnknownShuffleType(9))
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[2, 42, 1, 0, 0, 2])),
+            Err(SettingsStringError::InvalidFlag(2))
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[4, 42, 1, 0, 0, 0, 0, 7])),
+            Err(SettingsStringError::UnknownAbilityShuffleType(7))
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[5, 42, 1, 0, 0, 0, 0, 0, 7])),
+            Err(SettingsStringError::UnknownEnemyShuffleType(7))
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[7, 42, 1, 0, 0, 0, 0, 0, 0, 0, 7])),
+            Err(SettingsStringError::UnknownMusicShuffleType(7))
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[8, 42, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4])),
+            Err(SettingsStringError::UnknownKirbyColorType(4))
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[8, 42, 1, 0, 0, 0, 0, 0, 0, 0, 0, 2, 99, 0, 0])),
+            Err(SettingsStringError::UnknownSprayPaint(99))
+        ));
+        assert!(matches!(
+            Config::from_settings_string(&encode(&[
+                1, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F
+            ])),
+            Err(SettingsStringError::NumberTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_parse_invalid_plando() {
+        assert!(matches!(
+            Plando::parse(r#"{"connections": [{"door": "a"}]}"#),
+            Err(PlandoParseError::Json(_))
+        ));
+        assert!(matches!(
+            Plando::parse(r#"(connections: [(door: "a")])"#),
+            Err(PlandoParseError::Ron(_))
+        ));
+    }
 }
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+type Address = usize;
+
+// Where an enemy can be placed without getting stuck or dying straight away. Enemies only replace
+// enemies of the same class.
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Hash, PartialEq, Eq)]
+pub enum EnemyClass {
+    Ground,
+    Flying,
+    // Enemies that can be placed underwater
+    Water,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Enemy {
+    pub name: String,
+    // Bytes that make a slot spawn this enemy
+    pub value: Vec<u8>,
+    pub class: EnemyClass,
+}
+
+// A place in a room where an enemy spawns
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EnemySlot<IDType> {
+    // e.g. "RRoute: 1st enemy in 3rd room"
+    pub name: String,
+    // The node the enemy is at
+    pub node: IDType,
+    // The enemy in the slot, vanilla until the enemies are shuffled
+    pub enemy: Enemy,
+    // Where the enemy of the slot is stored in the ROM
+    pub addresses: Vec<Address>,
+}
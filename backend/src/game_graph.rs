@@ -1,40 +1,91 @@
 use crate::{
+    ability::{Ability, AbilitySource, Requirement},
+    boss::{Boss, BossRoom},
+    enemy::{Enemy, EnemySlot},
     graph::{
-        BaseEdgeSwapError, DoorData, EdgeSwapError, GetEdgeEndpointsError, Graph, SwapEdgeIndices,
+        AbilitySourceData, BaseEdgeSwapError, BossData, ChestData, Connection, DoorData, EdgePool,
+        EdgeSwapError, EnemyData, ExcludeEdgeError, GetEdgeEndpointsError, Graph, MusicData,
+        PaletteData, PinEdgeError, Placement, Reachability, SetBossError, Sphere, SwapEdgeIndices,
     },
-    rng::{ChooseMultipleFill, RandomBool},
+    item::{Chest, Item},
+    music::{MusicSlot, Track},
+    palette::{Kirby, KirbyPalette, Rgb},
 };
 use linked_hash_set::LinkedHashSet;
 use petgraph::{
-    algo,
     graph::{EdgeIndex, NodeIndex},
     stable_graph::StableDiGraph,
-    Direction, IntoWeightedEdge,
+    visit::EdgeRef,
+    Direction,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 type Address = usize;
 type Destination = [u8; 4];
 type NodeID = String;
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct StaticEdge<IDType> {
     pub start: IDType,
     pub end: IDType,
     pub two_way: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<Requirement>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct DynamicEdge<IDType> {
     pub start: IDType,
     pub end: IDType,
     pub two_way: bool,
+    // Requirements belong to the door Kirby leaves through, so they stay with the start of the
+    // edge when it is swapped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<Requirement>,
+    // Whether this edge leads from one area into another, e.g. from Rainbow Route into Moonlight
+    // Mansion. The hub mirrors aren't in the game data, so they aren't among these.
+    #[serde(default)]
+    pub area_connection: bool,
+    // Excluded edges keep their vanilla connection in every seed
+    #[serde(default)]
+    pub excluded: bool,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct GraphData<IDType> {
+    // Bumped whenever the game data changes, since the same settings can then give a different seed
+    pub version: u32,
+    // Door data of the USA release, the only one the randomizer supports
     pub door_data: HashMap<NodeID, (Destination, Vec<Address>)>,
+    // Enemies and other places where Kirby can pick up copy abilities
+    #[serde(default)]
+    pub ability_sources: Vec<AbilitySource<IDType>>,
+    // The values the game uses for each ability
+    #[serde(default)]
+    pub ability_ids: HashMap<Ability, u8>,
+    // Treasure chests and their vanilla items
+    #[serde(default)]
+    pub chests: Vec<Chest<IDType>>,
+    // Places in rooms where enemies spawn and their vanilla enemies
+    #[serde(default)]
+    pub enemy_slots: Vec<EnemySlot<IDType>>,
+    // Doors leading into boss fights and their vanilla bosses
+    #[serde(default)]
+    pub boss_rooms: Vec<BossRoom<IDType>>,
+    // Areas and rooms with their own background music, and their vanilla tracks
+    #[serde(default)]
+    pub music_slots: Vec<MusicSlot>,
+    // Palette tables of Kirby and the helpers
+    #[serde(default)]
+    pub kirby_palettes: Vec<KirbyPalette>,
+    pub start: IDType,
+    // The game is beatable when every goal can be reached from the start
+    pub goals: Vec<IDType>,
+    // Nodes the game takes Kirby out of through something the graph doesn't model, like the warp
+    // star at the end of a goal room
+    #[serde(default)]
+    pub warps: Vec<IDType>,
     pub static_edges: Vec<StaticEdge<IDType>>,
     pub dynamic_edges: Vec<DynamicEdge<IDType>>,
 }
@@ -54,35 +105,78 @@ impl From<SwapEdge> for SwapEdgeIndices {
     }
 }
 
+// Which swappable edges get shuffled, and which of them can be swapped with each other
+#[derive(Copy, Clone)]
+enum PoolRestriction {
+    None,
+    Areas,
+    AreaConnections,
+}
+
 #[derive(Clone)]
 pub struct GameGraph {
+    version: u32,
     door_data: HashMap<NodeID, (Destination, Vec<Address>)>,
-    base_graph: StableDiGraph<NodeID, ()>,
+    ability_sources: Vec<AbilitySource<NodeID>>,
+    // Indices into `ability_sources` for each node, so reachability doesn't scan every source
+    ability_source_nodes: HashMap<NodeID, Vec<usize>>,
+    ability_ids: HashMap<Ability, u8>,
+    chests: Vec<Chest<NodeID>>,
+    enemy_slots: Vec<EnemySlot<NodeID>>,
+    boss_rooms: Vec<BossRoom<NodeID>>,
+    music_slots: Vec<MusicSlot>,
+    kirby_palettes: Vec<KirbyPalette>,
+    start: NodeID,
+    goals: Vec<NodeID>,
+    warps: Vec<NodeID>,
+    base_graph: StableDiGraph<NodeID, Option<Requirement>>,
     node_map: HashMap<NodeID, NodeIndex>,
 
     // This needs to be a linked hash set because HashSet iteration order is non-deterministic,
     // which breaks seeded randomization
     swappable_edges: LinkedHashSet<SwapEdge>,
+    // Start nodes of the area connection edges. The start of an edge stays fixed when it is
+    // swapped, so these don't need updating.
+    area_connections: HashSet<NodeID>,
+    pool_restriction: PoolRestriction,
+    // Whether the boss room doors were taken out of the door shuffle for the boss shuffle
+    boss_rooms_separated: bool,
+    // Boss doors whose edge goes both ways, so Kirby leaves the boss room through them
+    two_way_boss_doors: HashSet<NodeID>,
+    // Doors and the entrances they were pinned to. These are no longer swappable.
+    pinned_edges: HashMap<NodeID, NodeID>,
+    // Doors that keep their vanilla entrance, either by default or for a single seed
+    excluded_edges: HashMap<NodeID, NodeID>,
+    // Entrances and a door that leads to each of them in the vanilla game
+    vanilla_doors: HashMap<NodeID, NodeID>,
 }
 
 fn build_base_graph(
     static_edges: Vec<StaticEdge<NodeID>>,
-) -> (StableDiGraph<NodeID, ()>, HashMap<NodeID, NodeIndex>) {
-    let mut base_graph_edges: Vec<(NodeID, NodeID)> = vec![];
-    for edge in static_edges {
-        base_graph_edges.push((edge.start.clone(), edge.end.clone()));
-        if edge.two_way {
-            base_graph_edges.push((edge.end, edge.start));
-        }
-    }
-
+) -> (
+    StableDiGraph<NodeID, Option<Requirement>>,
+    HashMap<NodeID, NodeIndex>,
+) {
     let mut graph = StableDiGraph::new();
     let mut node_map = HashMap::new();
 
-    for edge in base_graph_edges {
-        let (start_node_id, end_node_id, _): (NodeID, NodeID, (NodeID, NodeID)) =
-            edge.into_weighted_edge();
-        insert_edge(&mut graph, &mut node_map, start_node_id, end_node_id);
+    for edge in static_edges {
+        insert_edge(
+            &mut graph,
+            &mut node_map,
+            edge.start.clone(),
+            edge.end.clone(),
+            edge.requires.clone(),
+        );
+        if edge.two_way {
+            insert_edge(
+                &mut graph,
+                &mut node_map,
+                edge.end,
+                edge.start,
+                edge.requires,
+            );
+        }
     }
 
     (graph, node_map)
@@ -90,10 +184,11 @@ fn build_base_graph(
 
 // Insert an edge between two existing nodes. If nodes do not exist, create nodes and insert edge.
 fn insert_edge(
-    graph: &mut StableDiGraph<NodeID, ()>,
+    graph: &mut StableDiGraph<NodeID, Option<Requirement>>,
     node_map: &mut HashMap<NodeID, NodeIndex>,
     a: NodeID,
     b: NodeID,
+    requires: Option<Requirement>,
 ) -> EdgeIndex {
     let node_idx_a = node_map.get(&a).copied();
     let node_idx_b = node_map.get(&b).copied();
@@ -103,11 +198,11 @@ fn insert_edge(
         (None, Some(b_idx)) => (add_node(graph, node_map, a), b_idx),
         (None, None) => (add_node(graph, node_map, a), add_node(graph, node_map, b)),
     };
-    graph.add_edge(a_idx, b_idx, ())
+    graph.add_edge(a_idx, b_idx, requires)
 }
 
 fn add_node(
-    graph: &mut StableDiGraph<NodeID, ()>,
+    graph: &mut StableDiGraph<NodeID, Option<Requirement>>,
     node_map: &mut HashMap<NodeID, NodeIndex>,
     node_id: NodeID,
 ) -> NodeIndex {
@@ -117,7 +212,7 @@ fn add_node(
 }
 
 fn add_swappable_edges(
-    base_graph: &mut StableDiGraph<NodeID, ()>,
+    base_graph: &mut StableDiGraph<NodeID, Option<Requirement>>,
     node_map: &mut HashMap<NodeID, NodeIndex>,
     dynamic_edges: Vec<DynamicEdge<NodeID>>,
 ) -> LinkedHashSet<SwapEdge> {
@@ -126,33 +221,128 @@ fn add_swappable_edges(
 
     let mut swappable_edges = LinkedHashSet::new();
     for e in one_ways.into_iter() {
-        let idx = insert_edge(base_graph, node_map, e.start, e.end);
-        swappable_edges.insert(SwapEdge::OneWay(idx));
+        let excluded = e.excluded;
+        let idx = insert_edge(base_graph, node_map, e.start, e.end, e.requires);
+        if !excluded {
+            swappable_edges.insert(SwapEdge::OneWay(idx));
+        }
     }
 
     for e in two_ways.into_iter() {
-        let idx1 = insert_edge(base_graph, node_map, e.start.clone(), e.end.clone());
-        let idx2 = insert_edge(base_graph, node_map, e.end, e.start);
-        swappable_edges.insert(SwapEdge::TwoWay(idx1, idx2));
+        let excluded = e.excluded;
+        let idx1 = insert_edge(
+            base_graph,
+            node_map,
+            e.start.clone(),
+            e.end.clone(),
+            e.requires.clone(),
+        );
+        let idx2 = insert_edge(base_graph, node_map, e.end, e.start, e.requires);
+        if !excluded {
+            swappable_edges.insert(SwapEdge::TwoWay(idx1, idx2));
+        }
     }
 
     swappable_edges
 }
 
+// Splits edges into a pool of one-way edges and a pool of two-way edges
+fn pools_by_type(edges: Vec<SwapEdge>) -> Vec<EdgePool<SwapEdge>> {
+    let (two_ways, one_ways): (Vec<SwapEdge>, Vec<SwapEdge>) = edges
+        .into_iter()
+        .partition(|edge| matches!(edge, SwapEdge::TwoWay(_, _)));
+    vec![
+        EdgePool {
+            edges: one_ways,
+            two_way: false,
+        },
+        EdgePool {
+            edges: two_ways,
+            two_way: true,
+        },
+    ]
+    .into_iter()
+    .filter(|pool| !pool.edges.is_empty())
+    .collect()
+}
+
 impl GameGraph {
     pub fn new(graph_data: GraphData<NodeID>) -> Self {
         let (mut base_graph, mut node_map) = build_base_graph(graph_data.static_edges);
+        let area_connections = graph_data
+            .dynamic_edges
+            .iter()
+            .filter(|edge| edge.area_connection)
+            .map(|edge| edge.start.clone())
+            .collect();
+        let excluded_edges = graph_data
+            .dynamic_edges
+            .iter()
+            .filter(|edge| edge.excluded)
+            .flat_map(|edge| {
+                let mut doors = vec![(edge.start.clone(), edge.end.clone())];
+                if edge.two_way {
+                    doors.push((edge.end.clone(), edge.start.clone()));
+                }
+                doors
+            })
+            .collect();
+        let mut vanilla_doors: HashMap<NodeID, NodeID> = HashMap::new();
+        for edge in &graph_data.dynamic_edges {
+            vanilla_doors
+                .entry(edge.end.clone())
+                .or_insert_with(|| edge.start.clone());
+            if edge.two_way {
+                vanilla_doors
+                    .entry(edge.start.clone())
+                    .or_insert_with(|| edge.end.clone());
+            }
+        }
         let swappable_edges =
             add_swappable_edges(&mut base_graph, &mut node_map, graph_data.dynamic_edges);
 
+        let mut ability_source_nodes: HashMap<NodeID, Vec<usize>> = HashMap::new();
+        for (idx, source) in graph_data.ability_sources.iter().enumerate() {
+            ability_source_nodes
+                .entry(source.node.clone())
+                .or_default()
+                .push(idx);
+        }
+
         Self {
+            version: graph_data.version,
             door_data: graph_data.door_data,
+            ability_sources: graph_data.ability_sources,
+            ability_source_nodes,
+            ability_ids: graph_data.ability_ids,
+            chests: graph_data.chests,
+            enemy_slots: graph_data.enemy_slots,
+            boss_rooms: graph_data.boss_rooms,
+            music_slots: graph_data.music_slots,
+            kirby_palettes: graph_data.kirby_palettes,
+            start: graph_data.start,
+            goals: graph_data.goals,
+            warps: graph_data.warps,
             base_graph,
             node_map,
             swappable_edges,
+            area_connections,
+            pool_restriction: PoolRestriction::None,
+            boss_rooms_separated: false,
+            two_way_boss_doors: HashSet::new(),
+            pinned_edges: HashMap::new(),
+            excluded_edges,
+            vanilla_doors,
         }
     }
 
+    fn find_edge(&self, start: &NodeID, end: &NodeID) -> Option<EdgeIndex> {
+        self.node_map
+            .get(start)
+            .zip(self.node_map.get(end))
+            .and_then(|(start, end)| self.base_graph.find_edge(*start, *end))
+    }
+
     fn edge_node_ids(
         &self,
         idx: EdgeIndex,
@@ -172,6 +362,71 @@ impl GameGraph {
         Ok((node1_id.clone(), node2_id.clone()))
     }
 
+    // Finds the swappable edge with an orientation that matches, and returns it as it is stored
+    // along with the matching orientation. Two-way edges can match in either direction.
+    fn find_swappable_edge(
+        &self,
+        matches: impl Fn(&NodeID, &NodeID) -> bool,
+    ) -> Option<(SwapEdge, SwapEdge)> {
+        self.swappable_edges.iter().find_map(|edge| {
+            let orientations = match *edge {
+                SwapEdge::OneWay(_) => vec![*edge],
+                SwapEdge::TwoWay(idx1, idx2) => vec![*edge, SwapEdge::TwoWay(idx2, idx1)],
+            };
+            orientations
+                .into_iter()
+                .find(|oriented| {
+                    let idx = match *oriented {
+                        SwapEdge::OneWay(idx) | SwapEdge::TwoWay(idx, _) => idx,
+                    };
+                    self.edge_node_ids(idx)
+                        .is_ok_and(|(start, end)| matches(&start, &end))
+                })
+                .map(|oriented| (*edge, oriented))
+        })
+    }
+
+    fn is_area_connection(&self, edge: SwapEdge) -> bool {
+        match edge {
+            SwapEdge::OneWay(idx) | SwapEdge::TwoWay(idx, _) => self
+                .base_graph
+                .edge_endpoints(idx)
+                .is_some_and(|(start, _)| self.area_connections.contains(&self.base_graph[start])),
+        }
+    }
+
+    // One pool per pair of start and end areas, in the order the pools are first seen. Pairs are
+    // ordered, because swapping an edge with one that goes the opposite way would connect each
+    // area to itself.
+    fn get_area_pools(&self) -> Vec<EdgePool<SwapEdge>> {
+        let mut pools: Vec<EdgePool<SwapEdge>> = vec![];
+        let mut pool_indices: HashMap<(bool, &str, &str), usize> = HashMap::new();
+        for edge in &self.swappable_edges {
+            let (idx, two_way) = match *edge {
+                SwapEdge::OneWay(idx) => (idx, false),
+                SwapEdge::TwoWay(idx, _) => (idx, true),
+            };
+            let (start, end) = self
+                .base_graph
+                .edge_endpoints(idx)
+                .unwrap_or_else(|| panic!("Swappable edge {} has no endpoints", idx.index()));
+            let key = (
+                two_way,
+                area(&self.base_graph[start]),
+                area(&self.base_graph[end]),
+            );
+            let pool_idx = *pool_indices.entry(key).or_insert_with(|| {
+                pools.push(EdgePool {
+                    edges: vec![],
+                    two_way,
+                });
+                pools.len() - 1
+            });
+            pools[pool_idx].edges.push(*edge);
+        }
+        pools
+    }
+
     fn swap_one_ways(
         &mut self,
         idx: EdgeIndex,
@@ -211,36 +466,75 @@ impl GameGraph {
             .edge_node_ids(idx2)
             .map_err(BaseEdgeSwapError::EdgeEndpoints)?;
 
-        self.base_graph.remove_edge(idx1).ok_or_else(|| {
+        let requires1 = self.base_graph.remove_edge(idx1).ok_or_else(|| {
             BaseEdgeSwapError::MissingBaseEdge(edge1a.clone(), edge1b.clone(), idx1.index())
         })?;
-        self.base_graph.remove_edge(idx2).ok_or_else(|| {
+        let requires2 = self.base_graph.remove_edge(idx2).ok_or_else(|| {
             BaseEdgeSwapError::MissingBaseEdge(edge2a.clone(), edge2b.clone(), idx2.index())
         })?;
 
-        let new_edge_idx1 = insert_edge(&mut self.base_graph, &mut self.node_map, edge1a, edge2b);
-        let new_edge_idx2 = insert_edge(&mut self.base_graph, &mut self.node_map, edge2a, edge1b);
+        // Requirements stay with the start of each edge
+        let new_edge_idx1 = insert_edge(
+            &mut self.base_graph,
+            &mut self.node_map,
+            edge1a,
+            edge2b,
+            requires1,
+        );
+        let new_edge_idx2 = insert_edge(
+            &mut self.base_graph,
+            &mut self.node_map,
+            edge2a,
+            edge1b,
+            requires2,
+        );
 
         Ok((new_edge_idx1, new_edge_idx2))
     }
 }
 
+// Node IDs are prefixed by the area they are in, e.g. "RRoute: First door in main hub"
+pub fn area(node_id: &str) -> &str {
+    node_id.split(':').next().unwrap_or(node_id)
+}
+
 impl Graph<NodeID, SwapEdge> for GameGraph {
-    fn get_edges(&self) -> Vec<(NodeID, NodeID)> {
-        let mut res: Vec<(NodeID, NodeID)> = vec![];
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn start(&self) -> &NodeID {
+        &self.start
+    }
+
+    fn goals(&self) -> &[NodeID] {
+        &self.goals
+    }
+
+    fn get_connections(&self) -> Vec<Connection<NodeID>> {
+        let mut res: Vec<Connection<NodeID>> = vec![];
+        let mut push_shuffled = |(door, entrance): (NodeID, NodeID)| {
+            res.push(Connection {
+                door,
+                entrance,
+                placement: Placement::Shuffled,
+            })
+        };
         for edge in &self.swappable_edges {
             match edge {
-                SwapEdge::OneWay(idx) => res.push(self.edge_node_ids(*idx).unwrap_or_else(|e| {
-                    panic!("Error extracting string IDs for one way edge: {}", e)
-                })),
+                SwapEdge::OneWay(idx) => {
+                    push_shuffled(self.edge_node_ids(*idx).unwrap_or_else(|e| {
+                        panic!("Error extracting string IDs for one way edge: {}", e)
+                    }))
+                }
                 SwapEdge::TwoWay(idx1, idx2) => {
-                    res.push(self.edge_node_ids(*idx1).unwrap_or_else(|e| {
+                    push_shuffled(self.edge_node_ids(*idx1).unwrap_or_else(|e| {
                         panic!(
                             "Error extracting string IDs for two way edge (first): {}",
                             e
                         )
                     }));
-                    res.push(self.edge_node_ids(*idx2).unwrap_or_else(|e| {
+                    push_shuffled(self.edge_node_ids(*idx2).unwrap_or_else(|e| {
                         panic!(
                             "Error extracting string IDs for two way edge (second): {}",
                             e
@@ -250,9 +544,42 @@ impl Graph<NodeID, SwapEdge> for GameGraph {
             }
         }
 
-        for (start, end) in &res {
-            // TODO: Debug log level
-            println!("id pair: {}, {}", start, end);
+        let fixed = self
+            .pinned_edges
+            .iter()
+            .map(|edge| (edge, Placement::Pinned))
+            .chain(
+                self.excluded_edges
+                    .iter()
+                    .map(|edge| (edge, Placement::Excluded)),
+            );
+        for ((door, entrance), placement) in fixed {
+            res.push(Connection {
+                door: door.clone(),
+                entrance: entrance.clone(),
+                placement,
+            });
+        }
+        let boss_rooms: &[BossRoom<NodeID>] = if self.boss_rooms_separated {
+            &self.boss_rooms
+        } else {
+            &[]
+        };
+        for room in boss_rooms {
+            for door in &room.doors {
+                res.push(Connection {
+                    door: door.clone(),
+                    entrance: room.boss.entrance.clone(),
+                    placement: Placement::Boss,
+                });
+                if self.two_way_boss_doors.contains(door) {
+                    res.push(Connection {
+                        door: room.boss.entrance.clone(),
+                        entrance: door.clone(),
+                        placement: Placement::Boss,
+                    });
+                }
+            }
         }
 
         res
@@ -286,55 +613,319 @@ impl Graph<NodeID, SwapEdge> for GameGraph {
         Ok((new_edge1, new_edge2))
     }
 
-    fn pick_random_edges<R>(&self, rng: &mut R) -> Option<(SwapEdge, SwapEdge)>
-    where
-        R: RandomBool + ChooseMultipleFill,
-    {
-        use SwapEdge::*;
-        let edges = self.swappable_edges.iter();
-
-        let mut buf: Vec<SwapEdge> = Vec::with_capacity(2);
-        let num_edges = if rng.get_bool(0.5f64) {
-            rng.choose_multiple_fill(
-                edges.filter(|edge| matches!(edge, OneWay(_x))).cloned(),
-                &mut buf,
-            )
-        } else {
-            rng.choose_multiple_fill(
-                edges.filter(|edge| matches!(edge, TwoWay(_x, _y))).cloned(),
-                &mut buf,
-            )
-        };
+    fn decouple_edges(&mut self) {
+        self.swappable_edges = self
+            .swappable_edges
+            .iter()
+            .flat_map(|edge| match *edge {
+                SwapEdge::OneWay(idx) => vec![SwapEdge::OneWay(idx)],
+                SwapEdge::TwoWay(idx1, idx2) => {
+                    vec![SwapEdge::OneWay(idx1), SwapEdge::OneWay(idx2)]
+                }
+            })
+            .collect();
+    }
+
+    fn exclude_edge(&mut self, door: &NodeID) -> Result<(), ExcludeEdgeError> {
+        if self.excluded_edges.contains_key(door) {
+            return Ok(());
+        }
+        let (edge, oriented) = self
+            .find_swappable_edge(|start, _| start == door)
+            .ok_or_else(|| ExcludeEdgeError::UnknownDoor(door.clone()))?;
+        let (start, end) = self.get_edge_endpoints(oriented)?;
+        self.swappable_edges.remove(&edge);
+
+        if matches!(edge, SwapEdge::TwoWay(_, _)) {
+            self.excluded_edges.insert(end.clone(), start.clone());
+        }
+        self.excluded_edges.insert(start, end);
+        Ok(())
+    }
+
+    fn pin_edge(&mut self, door: &NodeID, entrance: &NodeID) -> Result<(), PinEdgeError> {
+        if self.excluded_edges.contains_key(door) {
+            return Err(PinEdgeError::ExcludedDoor(door.clone()));
+        }
+        if let Some((excluded_door, _)) = self
+            .excluded_edges
+            .iter()
+            .find(|(_, excluded_entrance)| *excluded_entrance == entrance)
+        {
+            return Err(PinEdgeError::ExcludedEntrance(
+                entrance.clone(),
+                excluded_door.clone(),
+            ));
+        }
+        if let Some(pinned_entrance) = self.pinned_edges.get(door) {
+            return Err(PinEdgeError::DoorAlreadyPinned(
+                door.clone(),
+                pinned_entrance.clone(),
+            ));
+        }
+        if let Some((pinned_door, _)) = self
+            .pinned_edges
+            .iter()
+            .find(|(_, pinned_entrance)| *pinned_entrance == entrance)
+        {
+            return Err(PinEdgeError::EntranceAlreadyPinned(
+                entrance.clone(),
+                pinned_door.clone(),
+            ));
+        }
+
+        let (from, oriented_from) = self
+            .find_swappable_edge(|start, _| start == door)
+            .ok_or_else(|| PinEdgeError::UnknownDoor(door.clone()))?;
+        let (to, oriented_to) = self
+            .find_swappable_edge(|_, end| end == entrance)
+            .ok_or_else(|| PinEdgeError::UnknownEntrance(entrance.clone()))?;
 
-        // If graph does not have enough edges to choose 2 to swap, then return None
-        if num_edges != 2 {
-            None
+        let swap_error =
+            |e: EdgeSwapError| PinEdgeError::EdgeSwap(door.clone(), entrance.clone(), e);
+        let two_way = match (oriented_from, oriented_to) {
+            (SwapEdge::OneWay(_), SwapEdge::OneWay(_)) => false,
+            (SwapEdge::TwoWay(_, _), SwapEdge::TwoWay(_, _)) => true,
+            _ => return Err(PinEdgeError::Mismatch(door.clone(), entrance.clone())),
+        };
+        if from == to {
+            // The door either already leads to the entrance, or is the other side of it
+            if oriented_from != oriented_to {
+                return Err(PinEdgeError::SelfConnection(door.clone()));
+            }
+            self.swappable_edges.remove(&from);
         } else {
-            Some((buf[0], buf[1]))
+            self.swappable_edges.remove(&from);
+            self.swappable_edges.remove(&to);
+            let (_, new_to) = match (oriented_from, oriented_to) {
+                (SwapEdge::OneWay(idx), SwapEdge::OneWay(other_idx)) => {
+                    self.swap_one_ways(idx, other_idx)
+                }
+                (SwapEdge::TwoWay(idx1, idx2), SwapEdge::TwoWay(other_idx1, other_idx2)) => {
+                    self.swap_two_ways(idx1, idx2, other_idx1, other_idx2)
+                }
+                _ => unreachable!("Edge types were checked above"),
+            }
+            .map_err(swap_error)?;
+            self.swappable_edges.insert(new_to);
+        }
+
+        self.pinned_edges.insert(door.clone(), entrance.clone());
+        if two_way {
+            self.pinned_edges.insert(entrance.clone(), door.clone());
         }
+        Ok(())
     }
 
-    fn get_unreachable_regions(&self) -> Vec<Vec<NodeID>> {
-        let condensed_graph = algo::condensation(
-            self.base_graph.map(|_, n| n, |_, e| e).into(),
-            /* make_acyclic */ true,
-        );
+    fn restrict_edges_to_areas(&mut self) {
+        self.pool_restriction = PoolRestriction::Areas;
+    }
 
-        condensed_graph
-            .externals(Direction::Incoming)
-            .map(|region_idx| {
-                condensed_graph
-                    .node_weight(region_idx)
-                    .unwrap_or_else(|| {
-                        panic!(
-                            "Region with index {} did not have a node weight",
-                            region_idx.index()
-                        )
-                    })
+    fn restrict_edges_to_area_connections(&mut self) {
+        self.pool_restriction = PoolRestriction::AreaConnections;
+    }
+
+    fn get_edge_pools(&self) -> Vec<EdgePool<SwapEdge>> {
+        match self.pool_restriction {
+            PoolRestriction::None => pools_by_type(self.swappable_edges.iter().copied().collect()),
+            PoolRestriction::Areas => self.get_area_pools(),
+            PoolRestriction::AreaConnections => pools_by_type(
+                self.swappable_edges
                     .iter()
-                    .map(|idx| (*idx).clone())
-                    .collect::<Vec<NodeID>>()
+                    .copied()
+                    .filter(|edge| self.is_area_connection(*edge))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn get_edge_endpoints(
+        &self,
+        edge: SwapEdge,
+    ) -> std::result::Result<(NodeID, NodeID), GetEdgeEndpointsError> {
+        match edge {
+            SwapEdge::OneWay(idx) | SwapEdge::TwoWay(idx, _) => self.edge_node_ids(idx),
+        }
+    }
+
+    fn get_fixed_edges(&self) -> Vec<(NodeID, NodeID)> {
+        let pooled: HashSet<EdgeIndex> = self
+            .get_edge_pools()
+            .into_iter()
+            .flat_map(|pool| pool.edges)
+            .flat_map(|edge| match edge {
+                SwapEdge::OneWay(idx) => vec![idx],
+                SwapEdge::TwoWay(idx1, idx2) => vec![idx1, idx2],
+            })
+            .collect();
+        self.base_graph
+            .edge_indices()
+            .filter(|idx| !pooled.contains(idx))
+            .filter_map(|idx| self.base_graph.edge_endpoints(idx))
+            .map(|(start, end)| (self.base_graph[start].clone(), self.base_graph[end].clone()))
+            .collect()
+    }
+
+    fn extend_reachable_nodes(
+        &self,
+        reachable: &mut HashSet<NodeID>,
+        start: &NodeID,
+        ignored_edges: &HashSet<SwapEdge>,
+    ) -> Vec<NodeID> {
+        let ignored_indices: HashSet<EdgeIndex> = ignored_edges
+            .iter()
+            .flat_map(|edge| match *edge {
+                SwapEdge::OneWay(idx) => vec![idx],
+                SwapEdge::TwoWay(idx1, idx2) => vec![idx1, idx2],
+            })
+            .collect();
+
+        let mut new_nodes = vec![];
+        let mut stack: Vec<NodeIndex> = self.node_map.get(start).copied().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let node_id = &self.base_graph[node];
+            if reachable.contains(node_id) {
+                continue;
+            }
+            reachable.insert(node_id.clone());
+            new_nodes.push(node_id.clone());
+            stack.extend(
+                self.base_graph
+                    .edges(node)
+                    .filter(|edge| !ignored_indices.contains(&edge.id()))
+                    .map(|edge| edge.target()),
+            );
+        }
+        new_nodes
+    }
+
+    fn get_reachability(&self, start: &NodeID) -> Reachability<NodeID> {
+        let mut abilities = HashSet::new();
+        let mut visited: HashSet<NodeIndex> = HashSet::new();
+        let mut previous: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+        let mut spheres = vec![];
+        let mut blocked: Vec<EdgeIndex> = vec![];
+        // Breadth-first, so that the first way a node is reached is also the shortest
+        let mut queue: VecDeque<(NodeIndex, Option<NodeIndex>)> = self
+            .node_map
+            .get(start)
+            .map(|&node| (node, None))
+            .into_iter()
+            .collect();
+
+        loop {
+            let mut sphere = Sphere {
+                nodes: vec![],
+                abilities: vec![],
+            };
+            while let Some((node, from)) = queue.pop_front() {
+                if !visited.insert(node) {
+                    continue;
+                }
+                if let Some(from) = from {
+                    previous.insert(node, from);
+                }
+                sphere.nodes.push(self.base_graph[node].clone());
+                if let Some(sources) = self.ability_source_nodes.get(&self.base_graph[node]) {
+                    for &source in sources {
+                        let ability = self.ability_sources[source].ability;
+                        if !abilities.contains(&ability) && !sphere.abilities.contains(&ability) {
+                            sphere.abilities.push(ability);
+                        }
+                    }
+                }
+                for edge in self.base_graph.edges(node) {
+                    match edge.weight() {
+                        Some(requires) if !requires.is_met(&abilities) => blocked.push(edge.id()),
+                        _ => queue.push_back((edge.target(), Some(node))),
+                    }
+                }
+            }
+            abilities.extend(sphere.abilities.iter().copied());
+            if !sphere.nodes.is_empty() {
+                spheres.push(sphere);
+            }
+
+            // Abilities picked up in this sphere can open edges that were blocked when first seen
+            let (opened, still_blocked): (Vec<EdgeIndex>, Vec<EdgeIndex>) =
+                blocked.into_iter().partition(|idx| {
+                    self.base_graph[*idx]
+                        .as_ref()
+                        .is_none_or(|requires| requires.is_met(&abilities))
+                });
+            blocked = still_blocked;
+            if opened.is_empty() {
+                break;
+            }
+            queue.extend(
+                opened
+                    .into_iter()
+                    .filter_map(|idx| self.base_graph.edge_endpoints(idx))
+                    .map(|(source, target)| (target, Some(source))),
+            );
+        }
+
+        let blocked_edges = blocked
+            .into_iter()
+            .filter_map(|idx| {
+                let (start, end) = self.base_graph.edge_endpoints(idx)?;
+                let requires = self.base_graph[idx].clone()?;
+                Some((
+                    self.base_graph[start].clone(),
+                    self.base_graph[end].clone(),
+                    requires,
+                ))
             })
+            .collect();
+
+        Reachability {
+            nodes: visited
+                .into_iter()
+                .map(|node| self.base_graph[node].clone())
+                .collect(),
+            abilities,
+            blocked_edges,
+            spheres,
+            previous: previous
+                .into_iter()
+                .map(|(node, from)| (self.base_graph[node].clone(), self.base_graph[from].clone()))
+                .collect(),
+        }
+    }
+
+    fn warps(&self) -> &[NodeID] {
+        &self.warps
+    }
+
+    fn get_nodes_reaching(
+        &self,
+        targets: &[NodeID],
+        abilities: &HashSet<Ability>,
+    ) -> HashSet<NodeID> {
+        let mut visited: HashSet<NodeIndex> = HashSet::new();
+        let mut stack: Vec<NodeIndex> = targets
+            .iter()
+            .filter_map(|target| self.node_map.get(target).copied())
+            .collect();
+        while let Some(node) = stack.pop() {
+            if !visited.insert(node) {
+                continue;
+            }
+            stack.extend(
+                self.base_graph
+                    .edges_directed(node, Direction::Incoming)
+                    .filter(|edge| {
+                        edge.weight()
+                            .as_ref()
+                            .is_none_or(|requires| requires.is_met(abilities))
+                    })
+                    .map(|edge| edge.source()),
+            );
+        }
+
+        visited
+            .into_iter()
+            .map(|node| self.base_graph[node].clone())
             .collect()
     }
 }
@@ -343,4 +934,519 @@ impl DoorData<NodeID> for GameGraph {
     fn door_data(&self) -> &HashMap<NodeID, (Destination, Vec<Address>)> {
         &self.door_data
     }
+
+    fn vanilla_door(&self, entrance: &NodeID) -> Option<&NodeID> {
+        self.vanilla_doors.get(entrance)
+    }
+}
+
+impl ChestData<NodeID> for GameGraph {
+    fn chests(&self) -> &[Chest<NodeID>] {
+        &self.chests
+    }
+
+    fn set_chest_item(&mut self, chest: usize, item: Item) {
+        self.chests[chest].item = item;
+    }
+}
+
+impl MusicData for GameGraph {
+    fn music_slots(&self) -> &[MusicSlot] {
+        &self.music_slots
+    }
+
+    fn set_slot_track(&mut self, slot: usize, track: Track) {
+        self.music_slots[slot].track = track;
+    }
+}
+
+impl PaletteData for GameGraph {
+    fn kirby_palettes(&self) -> &[KirbyPalette] {
+        &self.kirby_palettes
+    }
+
+    fn set_kirby_color(&mut self, kirby: Kirby, color: Rgb) {
+        for palette in self
+            .kirby_palettes
+            .iter_mut()
+            .filter(|palette| palette.kirby == kirby)
+        {
+            palette.color = Some(color);
+        }
+    }
+}
+
+impl BossData<NodeID> for GameGraph {
+    fn boss_rooms(&self) -> &[BossRoom<NodeID>] {
+        &self.boss_rooms
+    }
+
+    fn separate_boss_rooms(&mut self) {
+        for room in &self.boss_rooms {
+            for door in &room.doors {
+                let entrance = &room.boss.entrance;
+                self.excluded_edges.remove(door);
+                // Excluded two-way edges are kept in both directions
+                let mut two_way = self.excluded_edges.get(entrance) == Some(door);
+                if two_way {
+                    self.excluded_edges.remove(entrance);
+                }
+                let swap_edge = self.find_edge(door, entrance).and_then(|edge| {
+                    self.swappable_edges
+                        .iter()
+                        .copied()
+                        .find(|swap_edge| match *swap_edge {
+                            SwapEdge::OneWay(idx) => idx == edge,
+                            SwapEdge::TwoWay(idx1, idx2) => idx1 == edge || idx2 == edge,
+                        })
+                });
+                if let Some(swap_edge) = swap_edge {
+                    two_way |= matches!(swap_edge, SwapEdge::TwoWay(_, _));
+                    self.swappable_edges.remove(&swap_edge);
+                }
+                if two_way {
+                    self.two_way_boss_doors.insert(door.clone());
+                }
+            }
+        }
+        self.boss_rooms_separated = true;
+    }
+
+    fn set_room_boss(&mut self, room: usize, boss: Boss<NodeID>) -> Result<(), SetBossError> {
+        let old_entrance = self.boss_rooms[room].boss.entrance.clone();
+        for door in self.boss_rooms[room].doors.clone() {
+            let missing_edge = || SetBossError::MissingEdge(door.clone(), old_entrance.clone());
+            let edge = self
+                .find_edge(&door, &old_entrance)
+                .ok_or_else(missing_edge)?;
+            let requires = self.base_graph.remove_edge(edge).ok_or_else(missing_edge)?;
+            insert_edge(
+                &mut self.base_graph,
+                &mut self.node_map,
+                door.clone(),
+                boss.entrance.clone(),
+                requires,
+            );
+            // Leaving a two-way boss room takes Kirby back through the door
+            if self.two_way_boss_doors.contains(&door) {
+                let missing_exit = || SetBossError::MissingEdge(old_entrance.clone(), door.clone());
+                let exit = self
+                    .find_edge(&old_entrance, &door)
+                    .ok_or_else(missing_exit)?;
+                let requires = self.base_graph.remove_edge(exit).ok_or_else(missing_exit)?;
+                insert_edge(
+                    &mut self.base_graph,
+                    &mut self.node_map,
+                    boss.entrance.clone(),
+                    door,
+                    requires,
+                );
+            }
+        }
+        self.boss_rooms[room].boss = boss;
+        Ok(())
+    }
+}
+
+impl EnemyData<NodeID> for GameGraph {
+    fn enemy_slots(&self) -> &[EnemySlot<NodeID>] {
+        &self.enemy_slots
+    }
+
+    fn set_slot_enemy(&mut self, slot: usize, enemy: Enemy) {
+        self.enemy_slots[slot].enemy = enemy;
+    }
+}
+
+impl AbilitySourceData<NodeID> for GameGraph {
+    fn ability_sources(&self) -> &[AbilitySource<NodeID>] {
+        &self.ability_sources
+    }
+
+    fn set_source_ability(&mut self, source: usize, ability: Ability) {
+        self.ability_sources[source].ability = ability;
+    }
+
+    fn ability_ids(&self) -> &HashMap<Ability, u8> {
+        &self.ability_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boss::BossKind;
+
+    fn edge(start: &str, end: &str, two_way: bool) -> (NodeID, NodeID, bool) {
+        (start.to_string(), end.to_string(), two_way)
+    }
+
+    fn test_graph_data(
+        static_edges: Vec<(NodeID, NodeID, bool)>,
+        dynamic_edges: Vec<(NodeID, NodeID, bool)>,
+    ) -> GraphData<NodeID> {
+        GraphData {
+            version: 1,
+            door_data: HashMap::new(),
+            ability_sources: vec![],
+            ability_ids: HashMap::new(),
+            chests: vec![],
+            enemy_slots: vec![],
+            boss_rooms: vec![],
+            music_slots: vec![],
+            kirby_palettes: vec![],
+            start: "a".to_string(),
+            goals: vec![],
+            warps: vec![],
+            static_edges: static_edges
+                .into_iter()
+                .map(|(start, end, two_way)| StaticEdge {
+                    start,
+                    end,
+                    two_way,
+                    requires: None,
+                })
+                .collect(),
+            dynamic_edges: dynamic_edges
+                .into_iter()
+                .map(|(start, end, two_way)| DynamicEdge {
+                    start,
+                    end,
+                    two_way,
+                    requires: None,
+                    area_connection: false,
+                    excluded: false,
+                })
+                .collect(),
+        }
+    }
+
+    fn shuffled_edges(graph: &GameGraph) -> Vec<(NodeID, NodeID)> {
+        graph
+            .get_connections()
+            .into_iter()
+            .filter(|connection| connection.placement == Placement::Shuffled)
+            .map(|connection| (connection.door, connection.entrance))
+            .collect()
+    }
+
+    fn mixed_graph() -> GameGraph {
+        GameGraph::new(test_graph_data(
+            vec![edge("a", "b", true), edge("c", "d", true)],
+            vec![edge("b", "c", true), edge("d", "a", false)],
+        ))
+    }
+
+    #[test]
+    fn test_swap_mismatched_edges_fails() {
+        let mut graph = mixed_graph();
+        let edges: Vec<SwapEdge> = graph.swappable_edges.iter().cloned().collect();
+        match graph.swap_edges(edges[0], edges[1]) {
+            Err(EdgeSwapError::Mismatch(_, _)) => {}
+            _ => panic!("Swapping a one-way edge with a two-way edge should fail"),
+        }
+    }
+
+    #[test]
+    fn test_decouple_edges() -> Result<(), EdgeSwapError> {
+        let mut graph = mixed_graph();
+        graph.decouple_edges();
+        assert_eq!(graph.swappable_edges.len(), 3);
+        assert!(graph
+            .swappable_edges
+            .iter()
+            .all(|edge| matches!(edge, SwapEdge::OneWay(_))));
+
+        let edges: Vec<SwapEdge> = graph.swappable_edges.iter().cloned().collect();
+        graph.swap_edges(edges[0], edges[1])?;
+        let mut swapped_edges = shuffled_edges(&graph);
+        swapped_edges.sort();
+        assert_eq!(
+            swapped_edges,
+            vec![
+                ("b".to_string(), "a".to_string()),
+                ("c".to_string(), "b".to_string()),
+                ("d".to_string(), "c".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pin_edge() -> Result<(), PinEdgeError> {
+        let mut graph = GameGraph::new(test_graph_data(
+            vec![],
+            vec![
+                edge("a", "b", false),
+                edge("c", "d", false),
+                edge("e", "f", true),
+                edge("g", "h", true),
+            ],
+        ));
+        graph.pin_edge(&"a".to_string(), &"d".to_string())?;
+        graph.pin_edge(&"h".to_string(), &"f".to_string())?;
+
+        assert_eq!(graph.swappable_edges.len(), 2);
+        let mut edges = shuffled_edges(&graph);
+        edges.sort();
+        assert_eq!(
+            edges,
+            vec![
+                ("c".to_string(), "b".to_string()),
+                ("e".to_string(), "g".to_string()),
+                ("g".to_string(), "e".to_string()),
+            ]
+        );
+        let reachable = graph.get_reachable_nodes(&"h".to_string(), &HashSet::new());
+        assert_eq!(reachable, nodes(&["f", "h"]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pin_edge_errors() -> Result<(), PinEdgeError> {
+        let mut graph = GameGraph::new(test_graph_data(
+            vec![],
+            vec![
+                edge("a", "b", false),
+                edge("c", "d", false),
+                edge("e", "f", true),
+            ],
+        ));
+        let pin = |graph: &mut GameGraph, door: &str, entrance: &str| {
+            graph.pin_edge(&door.to_string(), &entrance.to_string())
+        };
+
+        assert!(matches!(
+            pin(&mut graph, "x", "b"),
+            Err(PinEdgeError::UnknownDoor(_))
+        ));
+        assert!(matches!(
+            pin(&mut graph, "a", "x"),
+            Err(PinEdgeError::UnknownEntrance(_))
+        ));
+        assert!(matches!(
+            pin(&mut graph, "a", "f"),
+            Err(PinEdgeError::Mismatch(_, _))
+        ));
+        assert!(matches!(
+            pin(&mut graph, "e", "e"),
+            Err(PinEdgeError::SelfConnection(_))
+        ));
+
+        pin(&mut graph, "a", "d")?;
+        assert!(matches!(
+            pin(&mut graph, "a", "b"),
+            Err(PinEdgeError::DoorAlreadyPinned(_, _))
+        ));
+        match pin(&mut graph, "c", "d") {
+            Err(e @ PinEdgeError::EntranceAlreadyPinned(_, _)) => {
+                assert_eq!(e.to_string(), "d is already pinned as the entrance of a")
+            }
+            _ => panic!("Pinning an entrance twice should fail"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_exclude_edge() -> Result<(), ExcludeEdgeError> {
+        let mut graph_data = test_graph_data(
+            vec![],
+            vec![
+                edge("a", "b", false),
+                edge("c", "d", false),
+                edge("e", "f", true),
+                edge("g", "h", true),
+            ],
+        );
+        graph_data.dynamic_edges[0].excluded = true;
+        let mut graph = GameGraph::new(graph_data);
+        assert_eq!(graph.swappable_edges.len(), 3);
+
+        graph.exclude_edge(&"h".to_string())?;
+        assert_eq!(graph.swappable_edges.len(), 2);
+        assert!(matches!(
+            graph.exclude_edge(&"x".to_string()),
+            Err(ExcludeEdgeError::UnknownDoor(_))
+        ));
+        assert!(matches!(
+            graph.pin_edge(&"c".to_string(), &"b".to_string()),
+            Err(PinEdgeError::ExcludedEntrance(_, _))
+        ));
+        assert!(matches!(
+            graph.pin_edge(&"g".to_string(), &"f".to_string()),
+            Err(PinEdgeError::ExcludedDoor(_))
+        ));
+        Ok(())
+    }
+
+    fn ability_graph(ability_sources: Vec<(&str, Ability)>) -> GameGraph {
+        let mut graph_data = test_graph_data(
+            vec![edge("a", "b", true), edge("a", "c", false)],
+            vec![edge("b", "d", false)],
+        );
+        graph_data.static_edges[1].requires = Some(Requirement::Ability(Ability::Cutter));
+        graph_data.dynamic_edges[0].requires = Some(Requirement::Ability(Ability::Bomb));
+        graph_data.ability_sources = ability_sources
+            .into_iter()
+            .map(|(node, ability)| AbilitySource {
+                node: node.to_string(),
+                ability,
+                addresses: vec![],
+            })
+            .collect();
+        GameGraph::new(graph_data)
+    }
+
+    fn nodes(ids: &[&str]) -> HashSet<NodeID> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_reachability_respects_requirements() {
+        let graph = ability_graph(vec![]);
+        let reachability = graph.get_reachability(&"a".to_string());
+        assert_eq!(reachability.nodes, nodes(&["a", "b"]));
+        assert_eq!(
+            reachability.blocked_edges,
+            vec![
+                (
+                    "a".to_string(),
+                    "c".to_string(),
+                    Requirement::Ability(Ability::Cutter)
+                ),
+                (
+                    "b".to_string(),
+                    "d".to_string(),
+                    Requirement::Ability(Ability::Bomb)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reachability_picks_up_abilities() {
+        let graph = ability_graph(vec![("b", Ability::Cutter), ("c", Ability::Bomb)]);
+        assert_eq!(
+            graph.get_reachability(&"a".to_string()).nodes,
+            nodes(&["a", "b", "c", "d"])
+        );
+    }
+
+    #[test]
+    fn test_swapped_edge_keeps_requirement() -> Result<(), EdgeSwapError> {
+        let mut graph_data = test_graph_data(
+            vec![edge("a", "b", true), edge("a", "c", true)],
+            vec![edge("b", "d", false), edge("c", "e", false)],
+        );
+        graph_data.dynamic_edges[0].requires = Some(Requirement::Ability(Ability::Bomb));
+        let mut graph = GameGraph::new(graph_data);
+
+        let edges: Vec<SwapEdge> = graph.swappable_edges.iter().cloned().collect();
+        graph.swap_edges(edges[0], edges[1])?;
+        assert_eq!(
+            graph.get_reachability(&"a".to_string()).nodes,
+            nodes(&["a", "b", "c", "d"])
+        );
+        Ok(())
+    }
+
+    fn boss(name: &str, entrance: &str) -> Boss<NodeID> {
+        Boss {
+            name: name.to_string(),
+            kind: BossKind::Boss,
+            entrance: entrance.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_set_room_boss() -> Result<(), SetBossError> {
+        let mut graph_data = test_graph_data(
+            vec![edge("a", "b", true), edge("a", "c", true)],
+            vec![edge("b", "d", false), edge("c", "e", false)],
+        );
+        graph_data.dynamic_edges[0].requires = Some(Requirement::Ability(Ability::Bomb));
+        graph_data.boss_rooms = vec![
+            BossRoom {
+                doors: vec!["b".to_string()],
+                boss: boss("Boss d", "d"),
+            },
+            BossRoom {
+                doors: vec!["c".to_string()],
+                boss: boss("Boss e", "e"),
+            },
+        ];
+        let mut graph = GameGraph::new(graph_data);
+        assert_eq!(graph.swappable_edges.len(), 2);
+
+        graph.separate_boss_rooms();
+        assert!(graph.swappable_edges.is_empty());
+        graph.set_room_boss(0, boss("Boss e", "e"))?;
+        graph.set_room_boss(1, boss("Boss d", "d"))?;
+        assert_eq!(graph.boss_rooms()[0].boss.name, "Boss e");
+        // The requirement stays with the door
+        assert_eq!(
+            graph.get_reachability(&"a".to_string()).nodes,
+            nodes(&["a", "b", "c", "d"])
+        );
+        let connections: Vec<(NodeID, NodeID, Placement)> = graph
+            .get_connections()
+            .into_iter()
+            .map(|connection| (connection.door, connection.entrance, connection.placement))
+            .collect();
+        assert_eq!(
+            connections,
+            vec![
+                ("b".to_string(), "e".to_string(), Placement::Boss),
+                ("c".to_string(), "d".to_string(), Placement::Boss),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_room_boss_two_way() -> Result<(), SetBossError> {
+        let mut graph_data = test_graph_data(
+            vec![edge("a", "b", true), edge("a", "c", true)],
+            vec![edge("b", "d", true), edge("c", "e", true)],
+        );
+        graph_data.dynamic_edges[1].excluded = true;
+        graph_data.boss_rooms = vec![
+            BossRoom {
+                doors: vec!["b".to_string()],
+                boss: boss("Boss d", "d"),
+            },
+            BossRoom {
+                doors: vec!["c".to_string()],
+                boss: boss("Boss e", "e"),
+            },
+        ];
+        let mut graph = GameGraph::new(graph_data);
+        assert_eq!(graph.swappable_edges.len(), 1);
+
+        graph.separate_boss_rooms();
+        assert!(graph.swappable_edges.is_empty());
+        assert!(graph.excluded_edges.is_empty());
+        graph.set_room_boss(0, boss("Boss e", "e"))?;
+        graph.set_room_boss(1, boss("Boss d", "d"))?;
+        // Kirby leaves each boss room through the door that leads into it
+        let id = |node: &str| node.to_string();
+        assert!(graph.find_edge(&id("e"), &id("b")).is_some());
+        assert!(graph.find_edge(&id("e"), &id("c")).is_none());
+        assert!(graph.find_edge(&id("d"), &id("c")).is_some());
+        let connections: Vec<(NodeID, NodeID)> = graph
+            .get_connections()
+            .into_iter()
+            .map(|connection| (connection.door, connection.entrance))
+            .collect();
+        assert_eq!(
+            connections,
+            vec![
+                ("b".to_string(), "e".to_string()),
+                ("e".to_string(), "b".to_string()),
+                ("c".to_string(), "d".to_string()),
+                ("d".to_string(), "c".to_string()),
+            ]
+        );
+        Ok(())
+    }
 }
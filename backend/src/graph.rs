@@ -1,5 +1,19 @@
-use crate::rng::{ChooseMultipleFill, RandomBool};
-use std::{cmp::Eq, collections::HashMap, fmt::Debug, hash::Hash};
+use crate::{
+    ability::{Ability, AbilitySource, Requirement},
+    boss::{Boss, BossRoom},
+    enemy::{Enemy, EnemySlot},
+    item::{Chest, Item},
+    music::{MusicSlot, Track},
+    palette::{Kirby, KirbyPalette, Rgb},
+};
+use serde::Serialize;
+use std::{
+    cmp::Eq,
+    collections::{HashMap, HashSet},
+    fmt,
+    fmt::Debug,
+    hash::Hash,
+};
 use thiserror::Error;
 
 type NodeID = String;
@@ -12,6 +26,15 @@ pub enum SwapEdgeIndices {
     TwoWay(usize, usize),
 }
 
+impl fmt::Display for SwapEdgeIndices {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SwapEdgeIndices::OneWay(idx) => write!(f, "one-way edge {}", idx),
+            SwapEdgeIndices::TwoWay(idx1, idx2) => write!(f, "two-way edge ({}, {})", idx1, idx2),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum GetEdgeEndpointsError {
     #[error("No endpoints found for edge with index {0}")]
@@ -32,25 +55,217 @@ pub enum BaseEdgeSwapError {
 
 #[derive(Error, Debug)]
 pub enum EdgeSwapError {
-    #[error("Failed to swap edge {0:?} with edge {1:?} of opposite type")]
+    #[error("Failed to swap {0} with {1} of opposite type")]
     Mismatch(SwapEdgeIndices, SwapEdgeIndices),
-    #[error("Edge {0:?} is not a swappable edge of the graph")]
+    #[error("{0} is not a swappable edge of the graph")]
     NonSwappableEdge(SwapEdgeIndices),
     #[error("Error swapping edges: {0}")]
     BaseEdgeSwap(#[from] BaseEdgeSwapError),
 }
 
+#[derive(Error, Debug)]
+pub enum PinEdgeError {
+    #[error("{0} is not a door that can be shuffled")]
+    UnknownDoor(NodeID),
+    #[error("{0} is not an entrance that can be shuffled")]
+    UnknownEntrance(NodeID),
+    #[error("{0} is already pinned to {1}")]
+    DoorAlreadyPinned(NodeID, NodeID),
+    #[error("{0} is already pinned as the entrance of {1}")]
+    EntranceAlreadyPinned(NodeID, NodeID),
+    #[error("Cannot connect {0} to {1}, because only one of them is a two-way door")]
+    Mismatch(NodeID, NodeID),
+    #[error("Cannot connect two-way door {0} to itself")]
+    SelfConnection(NodeID),
+    #[error("{0} is excluded from the shuffle")]
+    ExcludedDoor(NodeID),
+    #[error("{0} is excluded from the shuffle as the entrance of {1}")]
+    ExcludedEntrance(NodeID, NodeID),
+    #[error("Error pinning {0} to {1}: {2}")]
+    EdgeSwap(NodeID, NodeID, EdgeSwapError),
+}
+
+#[derive(Error, Debug)]
+pub enum ExcludeEdgeError {
+    #[error("{0} is not a door that can be shuffled")]
+    UnknownDoor(NodeID),
+    #[error("Error excluding edge: {0}")]
+    EdgeEndpoints(#[from] GetEdgeEndpointsError),
+}
+
+#[derive(Error, Debug)]
+pub enum SetBossError {
+    #[error("No edge leads from boss door {0} to {1}")]
+    MissingEdge(NodeID, NodeID),
+}
+
+// A group of swappable edges. An edge can only be swapped with another edge of its own pool.
+pub struct EdgePool<E> {
+    pub edges: Vec<E>,
+    // Two-way edges can be followed from either endpoint
+    pub two_way: bool,
+}
+
+// Nodes that become reachable once every ability from the earlier spheres has been picked up
+pub struct Sphere<N> {
+    pub nodes: Vec<N>,
+    // Abilities picked up in this sphere for the first time
+    pub abilities: Vec<Ability>,
+}
+
+// Where Kirby can get to from some start node
+pub struct Reachability<N> {
+    pub nodes: HashSet<N>,
+    // Every ability Kirby can pick up along the way
+    pub abilities: HashSet<Ability>,
+    // Edges leaving reachable nodes whose requirements were never met, as (start, end, requirement)
+    pub blocked_edges: Vec<(N, N, Requirement)>,
+    // The reachable nodes in the order Kirby can get to them
+    pub spheres: Vec<Sphere<N>>,
+    // The node each reachable node was first reached from. Following these back to the start
+    // gives the shortest path within the sphere a node was reached in.
+    pub previous: HashMap<N, N>,
+}
+
+impl<N: Clone + Eq + Hash> Reachability<N> {
+    // The nodes Kirby passes through on the way from the start to `node`, both included
+    pub fn path_to(&self, node: &N) -> Option<Vec<N>> {
+        if !self.nodes.contains(node) {
+            return None;
+        }
+        let mut path = vec![node.clone()];
+        while let Some(previous) = self.previous.get(&path[path.len() - 1]) {
+            path.push(previous.clone());
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+// How a door ended up connected to its entrance
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Placement {
+    Shuffled,
+    Pinned,
+    Excluded,
+    // Doors in front of a boss, which only the boss shuffle moves
+    Boss,
+}
+
+pub struct Connection<N> {
+    pub door: N,
+    pub entrance: N,
+    pub placement: Placement,
+}
+
 pub trait Graph<N, E> {
+    // Version of the game data the graph was built from
+    fn version(&self) -> u32;
+    // The node Kirby starts the game at
+    fn start(&self) -> &N;
+    // Nodes that must all be reachable from the start for the game to be beatable
+    fn goals(&self) -> &[N];
+
     fn swap_edges(&mut self, edge1: E, edge2: E) -> Result<(E, E), EdgeSwapError>;
-    fn pick_random_edges<R>(&self, rng: &mut R) -> Option<(E, E)>
+    // Splits every two-way swappable edge into two independent one-way edges
+    fn decouple_edges(&mut self);
+    // Keeps the vanilla connection of a door by taking its edge out of the shuffle
+    fn exclude_edge(&mut self, door: &N) -> Result<(), ExcludeEdgeError>;
+    // Connects a door to an entrance and takes the resulting edge out of the shuffle. Two-way doors
+    // get connected both ways.
+    fn pin_edge(&mut self, door: &N, entrance: &N) -> Result<(), PinEdgeError>;
+    // Groups swappable edges by the areas they connect, so that edges only get swapped with
+    // edges between the same areas
+    fn restrict_edges_to_areas(&mut self);
+    // Only shuffles edges that lead from one area into another and leaves every other edge vanilla
+    fn restrict_edges_to_area_connections(&mut self);
+    fn get_edge_pools(&self) -> Vec<EdgePool<E>>;
+    // The start node stays fixed when an edge is swapped; the end node is what gets shuffled
+    fn get_edge_endpoints(&self, edge: E) -> Result<(N, N), GetEdgeEndpointsError>;
+    // Every edge that isn't in any pool, as (start, end), whatever its requirements
+    fn get_fixed_edges(&self) -> Vec<(N, N)>;
+    // Adds every node reachable from `start` without using the ignored edges and returns the newly
+    // added nodes. Ability requirements are not checked. Nodes that are already in `reachable` are not explored again.
+    fn extend_reachable_nodes(
+        &self,
+        reachable: &mut HashSet<N>,
+        start: &N,
+        ignored_edges: &HashSet<E>,
+    ) -> Vec<N>
     where
-        R: RandomBool + ChooseMultipleFill;
-    fn get_edges(&self) -> Vec<(N, N)>;
-    fn get_unreachable_regions(&self) -> Vec<Vec<N>>
+        N: Eq + Hash,
+        E: Eq + Hash;
+    fn get_reachable_nodes(&self, start: &N, ignored_edges: &HashSet<E>) -> HashSet<N>
     where
-        N: Debug;
+        N: Eq + Hash,
+        E: Eq + Hash,
+    {
+        let mut reachable = HashSet::new();
+        self.extend_reachable_nodes(&mut reachable, start, ignored_edges);
+        reachable
+    }
+    // Nodes Kirby can get to from `start`, picking up abilities from ability sources on the way
+    // and only using edges whose requirements are met. Abilities picked up in one sphere only
+    // open edges for the next one.
+    fn get_reachability(&self, start: &N) -> Reachability<N>
+    where
+        N: Eq + Hash;
+    // Nodes that can get to any of the targets using edges whose requirements are met by the
+    // given abilities
+    fn get_nodes_reaching(&self, targets: &[N], abilities: &HashSet<Ability>) -> HashSet<N>
+    where
+        N: Eq + Hash;
+    // Nodes the game takes Kirby out of through something the graph doesn't model, like the warp
+    // star at the end of a goal room
+    fn warps(&self) -> &[N];
+    // Every door that leads to a shuffled, pinned or excluded entrance
+    fn get_connections(&self) -> Vec<Connection<N>>;
 }
 
 pub trait DoorData<N: Eq + Hash> {
     fn door_data(&self) -> &HashMap<N, (Destination, Vec<Address>)>;
+    // A door that leads to the entrance in the vanilla game, whatever the shuffle did to it
+    fn vanilla_door(&self, entrance: &N) -> Option<&N>;
+}
+
+pub trait AbilitySourceData<N> {
+    fn ability_sources(&self) -> &[AbilitySource<N>];
+    // Changes the ability given by the source at the given index of `ability_sources`
+    fn set_source_ability(&mut self, source: usize, ability: Ability);
+    fn ability_ids(&self) -> &HashMap<Ability, u8>;
+}
+
+pub trait BossData<N> {
+    fn boss_rooms(&self) -> &[BossRoom<N>];
+    // Takes the doors of the boss rooms out of the door shuffle, so only the boss shuffle moves
+    // them. Until then they are shuffled or excluded like any other door.
+    fn separate_boss_rooms(&mut self);
+    // Points the doors of the boss room at the given index of `boss_rooms` to the entrance of
+    // the boss
+    fn set_room_boss(&mut self, room: usize, boss: Boss<N>) -> Result<(), SetBossError>;
+}
+
+pub trait EnemyData<N> {
+    fn enemy_slots(&self) -> &[EnemySlot<N>];
+    // Puts an enemy in the slot at the given index of `enemy_slots`
+    fn set_slot_enemy(&mut self, slot: usize, enemy: Enemy);
+}
+
+pub trait MusicData {
+    fn music_slots(&self) -> &[MusicSlot];
+    // Plays a track in the slot at the given index of `music_slots`
+    fn set_slot_track(&mut self, slot: usize, track: Track);
+}
+
+pub trait PaletteData {
+    fn kirby_palettes(&self) -> &[KirbyPalette];
+    // Recolors every palette of the given Kirby
+    fn set_kirby_color(&mut self, kirby: Kirby, color: Rgb);
+}
+
+pub trait ChestData<N> {
+    fn chests(&self) -> &[Chest<N>];
+    // Puts an item in the chest at the given index of `chests`
+    fn set_chest_item(&mut self, chest: usize, item: Item);
 }
@@ -0,0 +1,27 @@
+use crate::ability::Requirement;
+use serde::{Deserialize, Serialize};
+
+type Address = usize;
+
+// Something Kirby can find in a treasure chest, e.g. a vitality heart or a spray paint
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Item {
+    pub name: String,
+    // Bytes that make a chest hold this item
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Chest<IDType> {
+    // e.g. "RRoute: Chest in 3rd room"
+    pub name: String,
+    // The node the chest is in
+    pub node: IDType,
+    // Abilities Kirby needs to get to the chest once he is at its node
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<Requirement>,
+    // The item in the chest, vanilla until the chests are shuffled
+    pub item: Item,
+    // Where the item of the chest is stored in the ROM
+    pub addresses: Vec<Address>,
+}
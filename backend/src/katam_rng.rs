@@ -1,5 +1,5 @@
-use crate::rng::{ChooseMultipleFill, RandomBool};
-use rand::{self, prelude::IteratorRandom, Rng, SeedableRng};
+use crate::rng::ChooseMultipleFill;
+use rand::{self, prelude::IteratorRandom, SeedableRng};
 
 pub struct KatamRng {
     rng: rand::rngs::StdRng,
@@ -11,11 +11,16 @@ impl KatamRng {
             rng: rand::rngs::StdRng::seed_from_u64(seed),
         }
     }
-}
 
-impl RandomBool for KatamRng {
-    fn get_bool(&mut self, p: f64) -> bool {
-        self.rng.gen_bool(p)
+    // A generator for one of several independent streams from the same seed. Drawing from one
+    // stream never changes what the others draw.
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng_seed = [0; 32];
+        rng_seed[..8].copy_from_slice(&seed.to_le_bytes());
+        rng_seed[8..16].copy_from_slice(&stream.to_le_bytes());
+        Self {
+            rng: rand::rngs::StdRng::from_seed(rng_seed),
+        }
     }
 }
 
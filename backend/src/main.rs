@@ -2,52 +2,191 @@
 extern crate rocket;
 
 use rocket::{
-    form::{Form, FromForm},
+    form::{self, Form, FromForm},
     fs::{relative, FileServer, TempFile},
     http::{ContentType, Header},
     State,
 };
-use std::{
-    env,
-    fs::{File, OpenOptions},
-};
+use std::{convert::TryFrom, env, fs::File, path::Path};
 use thiserror::Error;
 
+mod ability;
+mod boss;
 mod config;
+mod enemy;
 mod game_graph;
 mod graph;
+mod item;
 mod katam_rng;
+mod music;
+mod palette;
+mod patch;
+mod playthrough;
+mod race;
 mod randomizer;
 mod rng;
 mod rom;
 mod rom_file;
+mod rom_id;
+mod seed_hash;
+mod spoiler;
 
-use config::{Config, EntranceShuffleType};
+use config::{
+    AbilityShuffleType, Config, EnemyShuffleType, EntranceShuffleType, MusicShuffleType, Plando,
+};
 use game_graph::GameGraph;
+use palette::{Kirby, KirbyColor};
+use patch::OutputFormat;
+use race::{RaceError, RaceSecrets};
 
-const RANDOMIZED_ROM_NAME: &str = "katam_randomized.gba";
+// Followed by the extension of the output format
+const RANDOMIZED_ROM_NAME: &str = "katam_randomized";
+const SPOILER_TEXT_NAME: &str = "katam_spoiler.txt";
+const SPOILER_JSON_NAME: &str = "katam_spoiler.json";
+// Marks a race spoiler as released
+const RACE_UNLOCKED_NAME: &str = "unlocked";
+// Lets racers compare seeds without opening the spoiler log
+const SEED_HASH_HEADER: &str = "X-Seed-Hash";
+// The ID the spoiler log of a non-race seed can be downloaded with
+const SPOILER_ID_HEADER: &str = "X-Spoiler-Id";
 
 #[derive(Debug, FromForm)]
 struct Submit<'v> {
     #[field(validate = ext(ContentType::Binary))]
     rom_file: TempFile<'v>,
-    seed: u64,
-    entrance_shuffle_type: EntranceShuffleType,
+    // A shared settings string replaces every other setting below
+    settings: Option<String>,
+    seed: Option<u64>,
+    entrance_shuffle_type: Option<EntranceShuffleType>,
+    // RON or JSON file with connections to pin
+    plando_file: Option<TempFile<'v>>,
+    // One door per line
+    excluded_doors: Option<String>,
+    race: bool,
+    shuffle_chests: bool,
+    ability_shuffle_type: Option<AbilityShuffleType>,
+    enemy_shuffle_type: Option<EnemyShuffleType>,
+    shuffle_bosses: bool,
+    music_shuffle_type: Option<MusicShuffleType>,
+    // Vanilla, Random, a spray paint or #RRGGBB for each Kirby. A color that doesn't parse fails
+    // the whole form, rather than quietly keeping the vanilla color.
+    #[field(default_with = Some(KirbyColor::Vanilla))]
+    pink_kirby_color: KirbyColor,
+    #[field(default_with = Some(KirbyColor::Vanilla))]
+    yellow_kirby_color: KirbyColor,
+    #[field(default_with = Some(KirbyColor::Vanilla))]
+    red_kirby_color: KirbyColor,
+    #[field(default_with = Some(KirbyColor::Vanilla))]
+    green_kirby_color: KirbyColor,
+    // The randomized ROM, unless a patch is asked for
+    output: Option<OutputFormat>,
 }
 
-impl From<Form<Submit<'_>>> for Config {
-    fn from(form: Form<Submit<'_>>) -> Self {
-        Config {
-            seed: form.seed,
-            entrance_shuffle: form.entrance_shuffle_type,
+impl Submit<'_> {
+    fn kirby_color(&self, kirby: Kirby) -> KirbyColor {
+        match kirby {
+            Kirby::Pink => self.pink_kirby_color,
+            Kirby::Yellow => self.yellow_kirby_color,
+            Kirby::Red => self.red_kirby_color,
+            Kirby::Green => self.green_kirby_color,
         }
     }
 }
 
-#[derive(Responder)]
-#[response(content_type = "binary")]
+#[derive(Debug, FromForm)]
+struct ApplyPatch<'v> {
+    #[field(validate = ext(ContentType::Binary))]
+    rom_file: TempFile<'v>,
+    // BPS or IPS
+    patch_file: TempFile<'v>,
+}
+
+#[derive(Debug, FromForm)]
+struct RaceUnlock {
+    settings: String,
+    token: String,
+}
+
+#[derive(Debug, Error)]
+#[error("No {0} was given. Pick one or paste a settings string.")]
+struct MissingSettingError(&'static str);
+
+#[derive(Debug, Error)]
+#[error("Invalid form: {0}")]
+struct InvalidFormError(String);
+
+impl TryFrom<&Submit<'_>> for Config {
+    type Error = MissingSettingError;
+
+    fn try_from(form: &Submit<'_>) -> Result<Self, Self::Error> {
+        Ok(Config {
+            seed: form.seed.ok_or(MissingSettingError("seed"))?,
+            entrance_shuffle: form
+                .entrance_shuffle_type
+                .ok_or(MissingSettingError("entrance shuffle type"))?,
+            plando: Plando::default(),
+            excluded_doors: form
+                .excluded_doors
+                .iter()
+                .flat_map(|doors| doors.lines())
+                .map(str::trim)
+                .filter(|door| !door.is_empty())
+                .map(String::from)
+                .collect(),
+            race: form.race,
+            shuffle_chests: form.shuffle_chests,
+            ability_shuffle: form.ability_shuffle_type.unwrap_or(AbilityShuffleType::Off),
+            enemy_shuffle: form.enemy_shuffle_type.unwrap_or(EnemyShuffleType::Off),
+            shuffle_bosses: form.shuffle_bosses,
+            music_shuffle: form.music_shuffle_type.unwrap_or(MusicShuffleType::Off),
+            kirby_colors: [
+                form.kirby_color(Kirby::Pink),
+                form.kirby_color(Kirby::Yellow),
+                form.kirby_color(Kirby::Red),
+                form.kirby_color(Kirby::Green),
+            ],
+        })
+    }
+}
+
 struct RomResponder<'a> {
-    file: File,
+    rom: Vec<u8>,
+    content_disposition: Header<'a>,
+    seed_hash: Header<'a>,
+    // Race spoilers are only handed out once the race is unlocked
+    spoiler_id: Option<Header<'a>>,
+}
+
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for RomResponder<'o> {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        let mut response = rocket::Response::build_from(self.rom.respond_to(req)?);
+        response
+            .header(ContentType::Binary)
+            .header(self.content_disposition)
+            .header(self.seed_hash);
+        if let Some(spoiler_id) = self.spoiler_id {
+            response.header(spoiler_id);
+        }
+        response.ok()
+    }
+}
+
+// Where the spoiler log of a seed is kept. Race spoilers are found by the settings string once the
+// race is unlocked, while every other spoiler gets a random ID that only its requester is given.
+enum SpoilerKey {
+    Race(String),
+    Seed(String),
+}
+
+#[derive(Responder)]
+struct PatchedRomResponder<'a> {
+    rom: (ContentType, Vec<u8>),
+    content_disposition: Header<'a>,
+}
+
+#[derive(Responder)]
+struct SpoilerResponder<'a> {
+    file: (ContentType, File),
     content_disposition: Header<'a>,
 }
 
@@ -57,56 +196,493 @@ enum Error {
     Io(#[from] std::io::Error),
     #[error("Randomizer Error {0:?}")]
     KatamRando(#[from] randomizer::KatamRandoError),
+    #[error("Plando Error {0:?}")]
+    Plando(#[from] config::PlandoParseError),
+    #[error("Settings String Error {0:?}")]
+    SettingsString(#[from] config::SettingsStringError),
+    #[error("Missing Setting {0:?}")]
+    MissingSetting(#[from] MissingSettingError),
+    #[error("Form Error {0:?}")]
+    InvalidForm(#[from] InvalidFormError),
+    #[error("Race Error {0:?}")]
+    Race(#[from] RaceError),
+    #[error("ROM Error {0:?}")]
+    RomId(#[from] rom_id::RomIdError),
+    #[error("Patch Error {0:?}")]
+    Patch(#[from] patch::ApplyPatchError),
 }
 
 impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for randomizer::KatamRandoError {
     fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
-        rocket::http::Status::InternalServerError.respond_to(req)
+        use randomizer::KatamRandoError::*;
+        match self {
+            // The user's plando is at fault, so tell them what is wrong with it
+            Exclusion(_)
+            | Plando(_)
+            | PlandoSoftLock(_, _)
+            | UnbeatablePlando(_, _)
+            | FixedBossDoor(_) => {
+                (rocket::http::Status::BadRequest, self.to_string()).respond_to(req)
+            }
+            // The user turned on an option this build can't do anything with
+            MissingGameData(_, _) => {
+                (rocket::http::Status::BadRequest, self.to_string()).respond_to(req)
+            }
+            _ => rocket::http::Status::InternalServerError.respond_to(req),
+        }
+    }
+}
+
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for config::PlandoParseError {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        (rocket::http::Status::BadRequest, self.to_string()).respond_to(req)
+    }
+}
+
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for config::SettingsStringError {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        (rocket::http::Status::BadRequest, self.to_string()).respond_to(req)
+    }
+}
+
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for RaceError {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        let status = match self {
+            RaceError::Disabled => rocket::http::Status::BadRequest,
+            RaceError::WrongToken | RaceError::Locked => rocket::http::Status::Forbidden,
+        };
+        (status, self.to_string()).respond_to(req)
+    }
+}
+
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for rom_id::RomIdError {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        (rocket::http::Status::BadRequest, self.to_string()).respond_to(req)
+    }
+}
+
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for patch::ApplyPatchError {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        (rocket::http::Status::BadRequest, self.to_string()).respond_to(req)
+    }
+}
+
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for MissingSettingError {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        (rocket::http::Status::BadRequest, self.to_string()).respond_to(req)
+    }
+}
+
+impl<'r, 'o: 'r> rocket::response::Responder<'r, 'o> for InvalidFormError {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        (rocket::http::Status::BadRequest, self.to_string()).respond_to(req)
     }
 }
 
 #[post("/api/submit", data = "<form>")]
 async fn submit<'a>(
-    mut form: Form<Submit<'_>>,
+    form: Result<Form<Submit<'_>>, form::Errors<'_>>,
     graph: &State<GameGraph>,
+    race_secrets: &State<Option<RaceSecrets>>,
 ) -> Result<RomResponder<'a>, Error> {
-    let rom_path = format!("{}{}", relative!("/rom"), "katam_rom.gba");
-    form.rom_file.persist_to(&rom_path).await?;
-    let mut rom_file = OpenOptions::new().read(true).write(true).open(&rom_path)?;
-    let config: Config = form.into();
-    let rng = katam_rng::KatamRng::new(config.seed);
-    let rom = rom_file::RomFile {
-        rom_file: &mut rom_file,
+    // Tell the user which field was wrong instead of failing with a bare status
+    let form = form.map_err(|errors| InvalidFormError(errors.to_string()))?;
+    let rom = read_upload(&form.rom_file).await?;
+    // Reject anything but a clean dump before writing to it
+    rom_id::identify(&rom)?;
+    let settings = form
+        .settings
+        .as_deref()
+        .map(str::trim)
+        .filter(|settings| !settings.is_empty())
+        .map(String::from);
+    let config = match settings {
+        Some(settings) => Config::from_settings_string(&settings)?,
+        None => {
+            let plando = match form.plando_file.as_ref() {
+                Some(plando_file) => {
+                    let plando = String::from_utf8(read_upload(plando_file).await?)
+                        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+                    Plando::parse(&plando)?
+                }
+                None => Plando::default(),
+            };
+            let mut config = Config::try_from(&*form)?;
+            config.plando = plando;
+            config
+        }
     };
-    let mut graph_copy = (*graph).clone();
-    randomizer::randomize_katam(config, rng, rom, &mut graph_copy)?;
+    let output = form.output.unwrap_or(OutputFormat::Rom);
+    let spoiler_key = if config.race {
+        SpoilerKey::Race(race::race_id(&config))
+    } else {
+        SpoilerKey::Seed(format!("{:032x}", rand::random::<u128>()))
+    };
+    let (rom, spoiler) = randomize_rom(rom, output, config, race_secrets.as_ref(), graph)?;
+    let seed_hash = Header::new(SEED_HASH_HEADER, spoiler.hash.to_string());
+    std::fs::write(
+        spoiler_path(&spoiler_key, SPOILER_TEXT_NAME),
+        spoiler.to_string(),
+    )?;
+    std::fs::write(
+        spoiler_path(&spoiler_key, SPOILER_JSON_NAME),
+        spoiler.to_json().map_err(std::io::Error::from)?,
+    )?;
 
+    // The seed hash and spoiler ID go in the file name too, since a browser doesn't show response
+    // headers
+    let file_name = format!("{}_{}", RANDOMIZED_ROM_NAME, spoiler.hash.to_file_name());
+    let (file_name, spoiler_id) = match spoiler_key {
+        SpoilerKey::Race(_) => (file_name, None),
+        SpoilerKey::Seed(spoiler_id) => (
+            format!("{}_{}", file_name, spoiler_id),
+            Some(Header::new(SPOILER_ID_HEADER, spoiler_id)),
+        ),
+    };
     let content_disposition = Header::new(
         "Content-Disposition",
-        format!("attachment; filename=\"{}\"", RANDOMIZED_ROM_NAME),
+        format!(
+            "attachment; filename=\"{}.{}\"",
+            file_name,
+            output.extension()
+        ),
     );
 
     Ok(RomResponder {
-        file: rom_file,
+        rom,
+        content_disposition,
+        seed_hash,
+        spoiler_id,
+    })
+}
+
+// Reads an upload into memory. Rocket keeps each uploaded file at its own temporary path, so
+// nothing is shared with other requests.
+async fn read_upload(file: &TempFile<'_>) -> std::io::Result<Vec<u8>> {
+    match file {
+        TempFile::Buffered { content } => Ok(content.as_bytes().to_vec()),
+        file => match file.path() {
+            Some(path) => rocket::tokio::fs::read(path).await,
+            None => Ok(vec![]),
+        },
+    }
+}
+
+// Randomizes a ROM held in memory and gives back the output and the spoiler. Every call works on
+// its own copy of the ROM and the graph, so requests can run at the same time.
+fn randomize_rom(
+    mut rom: Vec<u8>,
+    output: OutputFormat,
+    config: Config,
+    race_secrets: Option<&RaceSecrets>,
+    graph: &GameGraph,
+) -> Result<(Vec<u8>, spoiler::Spoiler), Error> {
+    let rng_seed = race::rng_seed(&config, race_secrets)?;
+    let rng = katam_rng::KatamRng::new(rng_seed);
+    let music_rng = katam_rng::KatamRng::with_stream(rng_seed, music::MUSIC_RNG_STREAM);
+    let palette_rng = katam_rng::KatamRng::with_stream(rng_seed, palette::PALETTE_RNG_STREAM);
+    let rom_file = rom_file::RomFile {
+        rom_file: &mut rom,
+        output,
+    };
+    let mut graph_copy = graph.clone();
+    let spoiler = randomizer::randomize_katam(
+        config,
+        rng,
+        music_rng,
+        palette_rng,
+        rom_file,
+        &mut graph_copy,
+    )?;
+    Ok((rom, spoiler))
+}
+
+// Patches a clean ROM with a shared seed, without generating it again
+#[post("/api/patch", data = "<form>")]
+async fn apply_patch<'a>(form: Form<ApplyPatch<'_>>) -> Result<PatchedRomResponder<'a>, Error> {
+    let patched = patch_rom(
+        &read_upload(&form.rom_file).await?,
+        &read_upload(&form.patch_file).await?,
+    )?;
+    let content_disposition = Header::new(
+        "Content-Disposition",
+        format!(
+            "attachment; filename=\"{}.{}\"",
+            RANDOMIZED_ROM_NAME,
+            OutputFormat::Rom.extension()
+        ),
+    );
+    Ok(PatchedRomResponder {
+        rom: (ContentType::Binary, patched),
         content_disposition,
     })
 }
 
+// IPS patches can't tell whether they are applied to the right ROM, so the ROM is checked first
+fn patch_rom(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    rom_id::identify(rom)?;
+    Ok(rom::apply_patch(rom, patch)?)
+}
+
+// `katam-randomizer apply-patch <clean ROM> <patch> <output ROM>`
+fn apply_patch_command(args: &[String]) -> Result<(), String> {
+    let (rom_path, patch_path, output_path) = match args {
+        [rom_path, patch_path, output_path] => (rom_path, patch_path, output_path),
+        _ => {
+            return Err(
+                "Usage: katam-randomizer apply-patch <clean ROM> <patch> <output ROM>".to_string(),
+            )
+        }
+    };
+    let read =
+        |path: &str| std::fs::read(path).map_err(|e| format!("Error reading {}: {}", path, e));
+    // Show the messages of the ROM and patch errors rather than their debug output
+    let patched = patch_rom(&read(rom_path)?, &read(patch_path)?).map_err(|e| match e {
+        Error::RomId(e) => e.to_string(),
+        Error::Patch(e) => e.to_string(),
+        e => e.to_string(),
+    })?;
+    std::fs::write(output_path, patched)
+        .map_err(|e| format!("Error writing {}: {}", output_path, e))
+}
+
+fn spoiler_path(key: &SpoilerKey, file_name: &str) -> String {
+    match key {
+        SpoilerKey::Race(race_id) => format!("{}race_{}_{}", relative!("/rom"), race_id, file_name),
+        SpoilerKey::Seed(spoiler_id) => {
+            format!("{}seed_{}_{}", relative!("/rom"), spoiler_id, file_name)
+        }
+    }
+}
+
+// Spoiler log of a non-race seed, by the ID it was randomized with
+#[get("/api/spoiler/<format>?<id>")]
+fn get_spoiler<'a>(format: &str, id: &str) -> Result<Option<SpoilerResponder<'a>>, Error> {
+    // The ID ends up in a path, so only accept IDs the randomizer could have made
+    if id.len() != 32 || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    spoiler_responder(&SpoilerKey::Seed(id.to_ascii_lowercase()), format)
+}
+
+// Releases the spoiler of a race seed. Only the race organizer knows the unlock token.
+#[post("/api/race/unlock", data = "<form>")]
+fn unlock_race(
+    form: Form<RaceUnlock>,
+    race_secrets: &State<Option<RaceSecrets>>,
+) -> Result<Option<&'static str>, Error> {
+    race_secrets
+        .as_ref()
+        .ok_or(RaceError::Disabled)?
+        .check_unlock_token(&form.token)?;
+    let race_id = race::race_id(&Config::from_settings_string(&form.settings)?);
+    let key = SpoilerKey::Race(race_id);
+    if !Path::new(&spoiler_path(&key, SPOILER_TEXT_NAME)).exists() {
+        return Ok(None);
+    }
+    std::fs::write(spoiler_path(&key, RACE_UNLOCKED_NAME), "")?;
+    Ok(Some("Race spoiler unlocked"))
+}
+
+#[get("/api/race/spoiler/<format>?<settings>")]
+fn get_race_spoiler<'a>(
+    format: &str,
+    settings: &str,
+) -> Result<Option<SpoilerResponder<'a>>, Error> {
+    let key = SpoilerKey::Race(race::race_id(&Config::from_settings_string(settings)?));
+    if !Path::new(&spoiler_path(&key, RACE_UNLOCKED_NAME)).exists() {
+        return Err(RaceError::Locked.into());
+    }
+    spoiler_responder(&key, format)
+}
+
+fn spoiler_responder<'a>(
+    key: &SpoilerKey,
+    format: &str,
+) -> Result<Option<SpoilerResponder<'a>>, Error> {
+    let (content_type, file_name) = match format {
+        "txt" => (ContentType::Plain, SPOILER_TEXT_NAME),
+        "json" => (ContentType::JSON, SPOILER_JSON_NAME),
+        _ => return Ok(None),
+    };
+    let file = match File::open(spoiler_path(key, file_name)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let content_disposition = Header::new(
+        "Content-Disposition",
+        format!("attachment; filename=\"{}\"", file_name),
+    );
+
+    Ok(Some(SpoilerResponder {
+        file: (content_type, file),
+        content_disposition,
+    }))
+}
+
 type NodeID = String;
 
 fn load_game_data(path: &str) -> GameGraph {
     let file_contents = std::fs::read_to_string(path).expect("Error opening KatAM game data file.");
     let graph_data: game_graph::GraphData<NodeID> = ron::from_str(&file_contents)
         .unwrap_or_else(|e| panic!("Error deserializing KatAM game data: {}", e));
-    GameGraph::new(graph_data)
+    let graph = GameGraph::new(graph_data);
+    let report = randomizer::check_beatability(&graph);
+    if !report.is_beatable() {
+        panic!(
+            "The KatAM game data can't be beaten before shuffling: {:?}",
+            report.failed_goals
+        );
+    }
+    graph
 }
 
-#[rocket::launch]
-fn rocket() -> _ {
+#[rocket::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some("apply-patch") = args.first().map(String::as_str) {
+        if let Err(e) = apply_patch_command(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Err(e) = rocket().launch().await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn rocket() -> rocket::Rocket<rocket::Build> {
     let game_data = load_game_data(&env::var("KATAM_DATA_PATH").expect("Environment variable KATAM_DATA_PATH not set. Please set it to the path where the KatAM data file is located."));
 
     rocket::build()
-        .mount("/", rocket::routes![submit])
+        .mount(
+            "/",
+            rocket::routes![
+                submit,
+                apply_patch,
+                get_spoiler,
+                unlock_race,
+                get_race_spoiler
+            ],
+        )
         .mount("/", FileServer::from(relative!("../frontend")).rank(1))
         .manage(game_data)
+        .manage(RaceSecrets::from_env())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Large enough for every address in the game data
+    const ROM_SIZE: usize = 0x100_0000;
+
+    fn config(seed: u64) -> Config {
+        Config {
+            seed,
+            ..Config::default()
+        }
+    }
+
+    // Each request gets a ROM filled with its own byte and its own seed
+    fn randomize_request(request: u8, graph: &GameGraph) -> Vec<u8> {
+        let (rom, _) = randomize_rom(
+            vec![request; ROM_SIZE],
+            OutputFormat::Rom,
+            config(u64::from(request)),
+            None,
+            graph,
+        )
+        .unwrap_or_else(|e| panic!("Randomizing request {} failed: {}", request, e));
+        rom
+    }
+
+    fn test_game_graph() -> GameGraph {
+        load_game_data("doors.ron")
+    }
+
+    #[test]
+    fn test_concurrent_requests_are_isolated() {
+        let graph = test_game_graph();
+        let requests: Vec<u8> = (1..=4).collect();
+        let expected: Vec<Vec<u8>> = requests
+            .iter()
+            .map(|request| randomize_request(*request, &graph))
+            .collect();
+
+        let outputs: Vec<Vec<u8>> = thread::scope(|scope| {
+            let handles: Vec<_> = requests
+                .iter()
+                .map(|request| {
+                    let graph = &graph;
+                    scope.spawn(move || randomize_request(*request, graph))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("A request panicked"))
+                .collect()
+        });
+
+        for (request, (output, expected)) in requests.iter().zip(outputs.iter().zip(&expected)) {
+            // Comparing with assert_eq would print whole ROMs
+            assert!(
+                output == expected,
+                "Request {} gave a different ROM when run alongside the others",
+                request
+            );
+            assert_eq!(output.len(), ROM_SIZE);
+            assert_eq!(output[0], *request);
+        }
+        assert!(expected[0] != expected[1]);
+    }
+
+    #[test]
+    fn test_invalid_kirby_color_is_rejected() {
+        use rocket::{http::Status, local::blocking::Client};
+
+        let rocket = rocket::build()
+            .mount("/", rocket::routes![submit])
+            .manage(test_game_graph())
+            .manage(None::<RaceSecrets>);
+        let client = Client::tracked(rocket).expect("Error building test client");
+        let body = [
+            "--BOUNDARY",
+            "Content-Disposition: form-data; name=\"rom_file\"; filename=\"rom.gba\"",
+            "Content-Type: application/octet-stream",
+            "",
+            "not a rom",
+            "--BOUNDARY",
+            "Content-Disposition: form-data; name=\"pink_kirby_color\"",
+            "",
+            "#ZZZ",
+            "--BOUNDARY--",
+            "",
+        ]
+        .join("\r\n");
+        let response = client
+            .post("/api/submit")
+            .header(
+                ContentType::parse_flexible("multipart/form-data; boundary=BOUNDARY")
+                    .expect("Error parsing content type"),
+            )
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let message = response.into_string().unwrap_or_default();
+        assert!(message.contains("Unknown Kirby color"), "{}", message);
+    }
+
+    #[test]
+    fn test_spoiler_ids_are_checked() {
+        assert!(matches!(get_spoiler("txt", "../katam_spoiler"), Ok(None)));
+        assert!(matches!(get_spoiler("txt", &"z".repeat(32)), Ok(None)));
+        // A well-formed ID that was never handed out
+        assert!(matches!(get_spoiler("txt", &"0".repeat(32)), Ok(None)));
+    }
 }
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+type Address = usize;
+
+// Keeps the music shuffle apart from the RNG stream the logic draws from, so changing the music
+// never changes the rest of the seed
+pub const MUSIC_RNG_STREAM: u64 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Track {
+    pub name: String,
+    // Bytes that make a slot play this track
+    pub value: Vec<u8>,
+    // Short fanfares, like the one after beating a boss, rather than background music
+    #[serde(default)]
+    pub jingle: bool,
+}
+
+// An area or room with its own background music
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MusicSlot {
+    // e.g. "RRoute: Main theme"
+    pub name: String,
+    // The track played in the slot, vanilla until the music is shuffled
+    pub track: Track,
+    // Where the track of the slot is stored in the ROM
+    pub addresses: Vec<Address>,
+}
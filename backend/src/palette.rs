@@ -0,0 +1,277 @@
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+type Address = usize;
+
+// Keeps random colors apart from the RNG streams of the logic and the music, so recoloring Kirby
+// never changes the rest of the seed
+pub const PALETTE_RNG_STREAM: u64 = 2;
+
+// Shades written for each palette, from the outline to the highlight
+const PALETTE_SHADES: usize = 5;
+
+#[derive(Error, Debug, PartialEq)]
+#[error("Unknown Kirby color {0:?}. Use Vanilla, Random, a spray paint or #RRGGBB.")]
+pub struct ParseColorError(String);
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    // The GBA keeps 5 bits per channel, with blue in the high bits
+    pub fn to_bgr555(self) -> u16 {
+        (u16::from(self.b >> 3) << 10) | (u16::from(self.g >> 3) << 5) | u16::from(self.r >> 3)
+    }
+
+    fn map(self, f: impl Fn(u8) -> u8) -> Self {
+        Self::new(f(self.r), f(self.g), f(self.b))
+    }
+
+    fn darken(self, percent: u16) -> Self {
+        self.map(|c| (u16::from(c) * percent / 100) as u8)
+    }
+
+    fn lighten(self, percent: u16) -> Self {
+        self.map(|c| c + ((255 - u16::from(c)) * percent / 100) as u8)
+    }
+
+    // The bytes of a palette with shades of this color, as the game stores them
+    pub fn palette_bytes(self) -> Vec<u8> {
+        let shades: [Rgb; PALETTE_SHADES] = [
+            self.darken(35),
+            self.darken(65),
+            self,
+            self.lighten(35),
+            self.lighten(70),
+        ];
+        shades
+            .iter()
+            .flat_map(|shade| shade.to_bgr555().to_le_bytes())
+            .collect()
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = ParseColorError;
+
+    // Parses "#RRGGBB"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseColorError(s.to_string());
+        let hex = s.strip_prefix('#').ok_or_else(error)?;
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error());
+        }
+        let channel = |idx: usize| u8::from_str_radix(&hex[idx..idx + 2], 16).map_err(|_| error());
+        Ok(Self::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+// The spray paints Kirby can find in treasure chests
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub enum SprayPaint {
+    Pink,
+    Yellow,
+    Red,
+    Green,
+    Snow,
+    Carbon,
+    Ocean,
+    Sapphire,
+    Grape,
+    Emerald,
+    Orange,
+    Chocolate,
+    Cherry,
+    Chalk,
+    Citrus,
+    Lavender,
+}
+
+impl SprayPaint {
+    // In the order of their codes
+    pub const ALL: [SprayPaint; 16] = [
+        SprayPaint::Pink,
+        SprayPaint::Yellow,
+        SprayPaint::Red,
+        SprayPaint::Green,
+        SprayPaint::Snow,
+        SprayPaint::Carbon,
+        SprayPaint::Ocean,
+        SprayPaint::Sapphire,
+        SprayPaint::Grape,
+        SprayPaint::Emerald,
+        SprayPaint::Orange,
+        SprayPaint::Chocolate,
+        SprayPaint::Cherry,
+        SprayPaint::Chalk,
+        SprayPaint::Citrus,
+        SprayPaint::Lavender,
+    ];
+
+    pub fn color(self) -> Rgb {
+        match self {
+            SprayPaint::Pink => Rgb::new(0xF8, 0x98, 0xC0),
+            SprayPaint::Yellow => Rgb::new(0xF8, 0xE0, 0x40),
+            SprayPaint::Red => Rgb::new(0xE8, 0x30, 0x30),
+            SprayPaint::Green => Rgb::new(0x50, 0xC8, 0x48),
+            SprayPaint::Snow => Rgb::new(0xF0, 0xF0, 0xF8),
+            SprayPaint::Carbon => Rgb::new(0x58, 0x58, 0x60),
+            SprayPaint::Ocean => Rgb::new(0x40, 0x80, 0xE0),
+            SprayPaint::Sapphire => Rgb::new(0x28, 0x40, 0xB0),
+            SprayPaint::Grape => Rgb::new(0x90, 0x48, 0xC8),
+            SprayPaint::Emerald => Rgb::new(0x18, 0x98, 0x70),
+            SprayPaint::Orange => Rgb::new(0xF8, 0x90, 0x28),
+            SprayPaint::Chocolate => Rgb::new(0x88, 0x50, 0x30),
+            SprayPaint::Cherry => Rgb::new(0xC8, 0x20, 0x60),
+            SprayPaint::Chalk => Rgb::new(0xD8, 0xD0, 0xB8),
+            SprayPaint::Citrus => Rgb::new(0xC0, 0xE8, 0x30),
+            SprayPaint::Lavender => Rgb::new(0xC0, 0xA8, 0xF0),
+        }
+    }
+
+    // Codes used in settings strings. These must never change, or old settings strings break.
+    pub fn code(self) -> u8 {
+        SprayPaint::ALL
+            .iter()
+            .position(|paint| *paint == self)
+            .expect("Every spray paint is in ALL") as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        SprayPaint::ALL.get(usize::from(code)).copied()
+    }
+}
+
+// Kirby and the three helpers, who each have their own palette
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Kirby {
+    Pink,
+    Yellow,
+    Red,
+    Green,
+}
+
+impl Kirby {
+    // In the order of the colors in the settings
+    pub const ALL: [Kirby; 4] = [Kirby::Pink, Kirby::Yellow, Kirby::Red, Kirby::Green];
+}
+
+impl fmt::Display for Kirby {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} Kirby", self)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub enum KirbyColor {
+    Vanilla,
+    Random,
+    Preset(SprayPaint),
+    Custom(Rgb),
+}
+
+impl fmt::Display for KirbyColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KirbyColor::Vanilla => write!(f, "Vanilla"),
+            KirbyColor::Random => write!(f, "Random"),
+            KirbyColor::Preset(paint) => write!(f, "{:?}", paint),
+            KirbyColor::Custom(color) => write!(f, "{}", color),
+        }
+    }
+}
+
+impl FromStr for KirbyColor {
+    type Err = ParseColorError;
+
+    // Parses the names shown in the spoiler, ignoring case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("Vanilla") {
+            return Ok(KirbyColor::Vanilla);
+        }
+        if s.eq_ignore_ascii_case("Random") {
+            return Ok(KirbyColor::Random);
+        }
+        if let Some(paint) = SprayPaint::ALL
+            .iter()
+            .find(|paint| s.eq_ignore_ascii_case(&format!("{:?}", paint)))
+        {
+            return Ok(KirbyColor::Preset(*paint));
+        }
+        Ok(KirbyColor::Custom(s.parse()?))
+    }
+}
+
+// Empty form fields keep the vanilla colors
+impl<'v> FromFormField<'v> for KirbyColor {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        if field.value.trim().is_empty() {
+            return Ok(KirbyColor::Vanilla);
+        }
+        field
+            .value
+            .parse()
+            .map_err(|e: ParseColorError| form::Error::validation(e.to_string()).into())
+    }
+}
+
+// A palette table of one of the Kirbys
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct KirbyPalette {
+    pub kirby: Kirby,
+    // Where the palette is stored in the ROM
+    pub addresses: Vec<Address>,
+    // The color the palette is recolored with. Vanilla palettes are left untouched.
+    #[serde(default)]
+    pub color: Option<Rgb>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_bgr555() {
+        assert_eq!(Rgb::new(0xFF, 0, 0).to_bgr555(), 0x001F);
+        assert_eq!(Rgb::new(0, 0xFF, 0).to_bgr555(), 0x03E0);
+        assert_eq!(Rgb::new(0, 0, 0xFF).to_bgr555(), 0x7C00);
+        assert_eq!(Rgb::new(0x08, 0x10, 0x18).to_bgr555(), 0x0C41);
+        assert_eq!(Rgb::new(0xFF, 0xFF, 0xFF).palette_bytes().len(), 10);
+        assert_eq!(&Rgb::new(0xFF, 0, 0).palette_bytes()[4..6], &[0x1F, 0x00]);
+    }
+
+    #[test]
+    fn test_parse_kirby_color() {
+        assert_eq!("vanilla".parse(), Ok(KirbyColor::Vanilla));
+        assert_eq!("Random".parse(), Ok(KirbyColor::Random));
+        assert_eq!("carbon".parse(), Ok(KirbyColor::Preset(SprayPaint::Carbon)));
+        assert_eq!(
+            "#FF8800".parse(),
+            Ok(KirbyColor::Custom(Rgb::new(0xFF, 0x88, 0x00)))
+        );
+        assert_eq!(Rgb::new(0xFF, 0x88, 0x00).to_string(), "#FF8800");
+        assert!("#FF88".parse::<KirbyColor>().is_err());
+        assert!("FF8800".parse::<KirbyColor>().is_err());
+        assert!("Magenta".parse::<KirbyColor>().is_err());
+        for paint in SprayPaint::ALL.iter() {
+            assert_eq!(SprayPaint::from_code(paint.code()), Some(*paint));
+        }
+    }
+}
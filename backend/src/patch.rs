@@ -0,0 +1,554 @@
+use rocket::form::FromFormField;
+use std::convert::TryFrom;
+use thiserror::Error;
+
+// IPS offsets are 3 bytes, so IPS patches can't change anything past the first 16 MiB
+const IPS_MAX_OFFSET: usize = 0xFF_FFFF;
+const IPS_MAX_RECORD: usize = 0xFFFF;
+// A record at this offset would be read as the end of the patch
+const IPS_EOF_OFFSET: usize = 0x45_4F46;
+
+pub const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+
+pub const BPS_MAGIC: &[u8] = b"BPS1";
+// Checksums of the source, the target and the patch itself
+const BPS_FOOTER_SIZE: usize = 12;
+const BPS_SOURCE_READ: u64 = 0;
+const BPS_TARGET_READ: u64 = 1;
+const BPS_SOURCE_COPY: u64 = 2;
+
+// What the randomizer gives back: the randomized ROM, or a patch that turns the clean ROM into it
+#[derive(Copy, Clone, Debug, PartialEq, FromFormField)]
+pub enum OutputFormat {
+    Rom,
+    Bps,
+    Ips,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Rom => "gba",
+            OutputFormat::Bps => "bps",
+            OutputFormat::Ips => "ips",
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum IpsError {
+    #[error(
+        "IPS patches can't change byte {0:#x}, which is past the first 16 MiB. Use BPS instead."
+    )]
+    OffsetTooLarge(usize),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ApplyPatchError {
+    #[error("The patch is neither a BPS nor an IPS patch")]
+    UnknownFormat,
+    #[error("The patch ends unexpectedly")]
+    Truncated,
+    #[error("The patch is corrupt: it reads or writes past the end of a ROM")]
+    Corrupt,
+    #[error("The patch is damaged: its checksum is {actual:08X} instead of {expected:08X}")]
+    PatchChecksum { expected: u32, actual: u32 },
+    #[error("The patch is for a ROM of {expected} bytes, but the ROM has {actual} bytes")]
+    SourceSize { expected: usize, actual: usize },
+    #[error(
+        "The patch is for a different ROM: the ROM's CRC32 is {actual:08X}, but the patch expects \
+         {expected:08X}"
+    )]
+    SourceChecksum { expected: u32, actual: u32 },
+    #[error("The patched ROM has {actual} bytes instead of {expected}")]
+    TargetSize { expected: usize, actual: usize },
+    #[error(
+        "The patched ROM has CRC32 {actual:08X} instead of {expected:08X}, so the patch didn't \
+         apply cleanly"
+    )]
+    TargetChecksum { expected: u32, actual: u32 },
+}
+
+// Runs of bytes that differ between the source and the target, as (start, end) offsets in the
+// target. Bytes past the end of the source always differ.
+fn changed_runs(source: &[u8], target: &[u8]) -> Vec<(usize, usize)> {
+    let mut runs = vec![];
+    let mut start = None;
+    for (offset, byte) in target.iter().enumerate() {
+        let changed = source.get(offset) != Some(byte);
+        match (changed, start) {
+            (true, None) => start = Some(offset),
+            (false, Some(run_start)) => {
+                runs.push((run_start, offset));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(run_start) = start {
+        runs.push((run_start, target.len()));
+    }
+    runs
+}
+
+// BPS numbers are varints where each continuation also adds one, so every number has exactly one
+// encoding
+fn write_bps_number(patch: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let bits = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            patch.push(0x80 | bits);
+            return;
+        }
+        patch.push(bits);
+        value -= 1;
+    }
+}
+
+struct PatchReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl PatchReader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], ApplyPatchError> {
+        if len > self.bytes.len() {
+            return Err(ApplyPatchError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, ApplyPatchError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn big_endian(&mut self, len: usize) -> Result<usize, ApplyPatchError> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0, |value, byte| (value << 8) | usize::from(*byte)))
+    }
+
+    fn bps_number(&mut self) -> Result<u64, ApplyPatchError> {
+        let mut value = 0u64;
+        let mut shift = 1u64;
+        loop {
+            let byte = self.byte()?;
+            value = u64::from(byte & 0x7F)
+                .checked_mul(shift)
+                .and_then(|bits| value.checked_add(bits))
+                .ok_or(ApplyPatchError::Corrupt)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or(ApplyPatchError::Corrupt)?;
+            value = value.checked_add(shift).ok_or(ApplyPatchError::Corrupt)?;
+        }
+    }
+
+    fn bps_len(&mut self) -> Result<usize, ApplyPatchError> {
+        usize::try_from(self.bps_number()?).map_err(|_| ApplyPatchError::Corrupt)
+    }
+
+    // Copy offsets are relative, with the sign in the lowest bit
+    fn bps_offset(&mut self, offset: usize) -> Result<usize, ApplyPatchError> {
+        let number = self.bps_number()?;
+        let distance = usize::try_from(number >> 1).map_err(|_| ApplyPatchError::Corrupt)?;
+        if number & 1 == 0 {
+            offset.checked_add(distance)
+        } else {
+            offset.checked_sub(distance)
+        }
+        .ok_or(ApplyPatchError::Corrupt)
+    }
+}
+
+fn write_bps_action(patch: &mut Vec<u8>, action: u64, len: usize) {
+    write_bps_number(patch, ((len as u64 - 1) << 2) | action);
+}
+
+// Unchanged bytes are read from the source and changed bytes are stored in the patch
+pub fn create_bps(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = BPS_MAGIC.to_vec();
+    write_bps_number(&mut patch, source.len() as u64);
+    write_bps_number(&mut patch, target.len() as u64);
+    // No metadata
+    write_bps_number(&mut patch, 0);
+
+    let mut offset = 0;
+    for (start, end) in changed_runs(source, target) {
+        if start > offset {
+            write_bps_action(&mut patch, BPS_SOURCE_READ, start - offset);
+        }
+        write_bps_action(&mut patch, BPS_TARGET_READ, end - start);
+        patch.extend_from_slice(&target[start..end]);
+        offset = end;
+    }
+    if target.len() > offset {
+        write_bps_action(&mut patch, BPS_SOURCE_READ, target.len() - offset);
+    }
+
+    patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+    patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+    let patch_crc32 = crc32fast::hash(&patch);
+    patch.extend_from_slice(&patch_crc32.to_le_bytes());
+    patch
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    let mut le_bytes = [0; 4];
+    le_bytes.copy_from_slice(bytes);
+    u32::from_le_bytes(le_bytes)
+}
+
+// Checks the size and checksum of the source and of the result, so a patch for another ROM is
+// never applied
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, ApplyPatchError> {
+    if !patch.starts_with(BPS_MAGIC) {
+        return Err(ApplyPatchError::UnknownFormat);
+    }
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE {
+        return Err(ApplyPatchError::Truncated);
+    }
+    let (body, footer) = patch.split_at(patch.len() - BPS_FOOTER_SIZE);
+    let source_crc32 = read_u32_le(&footer[..4]);
+    let target_crc32 = read_u32_le(&footer[4..8]);
+    let patch_crc32 = read_u32_le(&footer[8..]);
+    let actual = crc32fast::hash(&patch[..patch.len() - 4]);
+    if actual != patch_crc32 {
+        return Err(ApplyPatchError::PatchChecksum {
+            expected: patch_crc32,
+            actual,
+        });
+    }
+
+    let mut reader = PatchReader {
+        bytes: &body[BPS_MAGIC.len()..],
+    };
+    let source_size = reader.bps_len()?;
+    let target_size = reader.bps_len()?;
+    let metadata_size = reader.bps_len()?;
+    reader.take(metadata_size)?;
+    if source.len() != source_size {
+        return Err(ApplyPatchError::SourceSize {
+            expected: source_size,
+            actual: source.len(),
+        });
+    }
+    let actual = crc32fast::hash(source);
+    if actual != source_crc32 {
+        return Err(ApplyPatchError::SourceChecksum {
+            expected: source_crc32,
+            actual,
+        });
+    }
+
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+    while !reader.bytes.is_empty() {
+        let command = reader.bps_number()?;
+        let len = usize::try_from((command >> 2) + 1).map_err(|_| ApplyPatchError::Corrupt)?;
+        if len > target_size - target.len() {
+            return Err(ApplyPatchError::Corrupt);
+        }
+        match command & 3 {
+            BPS_SOURCE_READ => {
+                let start = target.len();
+                let bytes = source
+                    .get(start..start + len)
+                    .ok_or(ApplyPatchError::Corrupt)?;
+                target.extend_from_slice(bytes);
+            }
+            BPS_TARGET_READ => target.extend_from_slice(reader.take(len)?),
+            BPS_SOURCE_COPY => {
+                source_offset = reader.bps_offset(source_offset)?;
+                let bytes = source
+                    .get(source_offset..source_offset + len)
+                    .ok_or(ApplyPatchError::Corrupt)?;
+                target.extend_from_slice(bytes);
+                source_offset += len;
+            }
+            // The only action left copies from the target
+            _ => {
+                target_offset = reader.bps_offset(target_offset)?;
+                // The copy can overlap the bytes it writes, so it has to go one byte at a time
+                for _ in 0..len {
+                    let byte = *target.get(target_offset).ok_or(ApplyPatchError::Corrupt)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(ApplyPatchError::TargetSize {
+            expected: target_size,
+            actual: target.len(),
+        });
+    }
+    let actual = crc32fast::hash(&target);
+    if actual != target_crc32 {
+        return Err(ApplyPatchError::TargetChecksum {
+            expected: target_crc32,
+            actual,
+        });
+    }
+    Ok(target)
+}
+
+// IPS patches don't know which ROM they are for, so the source should be checked beforehand
+pub fn apply_ips(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, ApplyPatchError> {
+    if !patch.starts_with(IPS_MAGIC) {
+        return Err(ApplyPatchError::UnknownFormat);
+    }
+    let mut reader = PatchReader {
+        bytes: &patch[IPS_MAGIC.len()..],
+    };
+    let mut target = source.to_vec();
+    loop {
+        if reader.bytes.starts_with(IPS_EOF) {
+            reader.take(IPS_EOF.len())?;
+            break;
+        }
+        let offset = reader.big_endian(3)?;
+        let (len, bytes) = match reader.big_endian(2)? {
+            // Records without a size repeat one byte
+            0 => {
+                let len = reader.big_endian(2)?;
+                (len, None)
+            }
+            len => (len, Some(reader.take(len)?)),
+        };
+        if target.len() < offset + len {
+            target.resize(offset + len, 0);
+        }
+        match bytes {
+            Some(bytes) => target[offset..offset + len].copy_from_slice(bytes),
+            None => {
+                let byte = reader.byte()?;
+                target[offset..offset + len]
+                    .iter_mut()
+                    .for_each(|b| *b = byte);
+            }
+        }
+    }
+    match reader.bytes.len() {
+        0 => {}
+        3 => {
+            let size = reader.big_endian(3)?;
+            if size > target.len() {
+                return Err(ApplyPatchError::Corrupt);
+            }
+            target.truncate(size);
+        }
+        _ => return Err(ApplyPatchError::Corrupt),
+    }
+    Ok(target)
+}
+
+pub fn create_ips(source: &[u8], target: &[u8]) -> Result<Vec<u8>, IpsError> {
+    let mut patch = IPS_MAGIC.to_vec();
+    for (mut start, end) in changed_runs(source, target) {
+        if start == IPS_EOF_OFFSET {
+            start -= 1;
+        }
+        while start < end {
+            if start > IPS_MAX_OFFSET {
+                return Err(IpsError::OffsetTooLarge(start));
+            }
+            let mut len = (end - start).min(IPS_MAX_RECORD);
+            if start + len == IPS_EOF_OFFSET && start + len < end {
+                len -= 1;
+            }
+            patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+            patch.extend_from_slice(&(len as u16).to_be_bytes());
+            patch.extend_from_slice(&target[start..start + len]);
+            start += len;
+        }
+    }
+    patch.extend_from_slice(IPS_EOF);
+    // Most IPS tools read a size after the end marker as the size to truncate the target to
+    if target.len() < source.len() {
+        if target.len() > IPS_MAX_OFFSET {
+            return Err(IpsError::OffsetTooLarge(target.len()));
+        }
+        patch.extend_from_slice(&(target.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bps_numbers() {
+        let encode = |value| {
+            let mut bytes = vec![];
+            write_bps_number(&mut bytes, value);
+            bytes
+        };
+        assert_eq!(encode(0), vec![0x80]);
+        assert_eq!(encode(0x7F), vec![0xFF]);
+        assert_eq!(encode(0x80), vec![0x00, 0x80]);
+        assert_eq!(encode(0x4000), vec![0x00, 0xFF]);
+        assert_eq!(encode(0x4080), vec![0x00, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn test_create_bps() {
+        let source = [0, 1, 2, 3];
+        let target = [0, 9, 2, 3, 4];
+        let patch = create_bps(&source, &target);
+        assert_eq!(
+            &patch[..patch.len() - 12],
+            // The header, then: read 1 byte from the source, write 9, read 2 bytes from the source,
+            // write 4
+            &[b'B', b'P', b'S', b'1', 0x84, 0x85, 0x80, 0x80, 0x81, 9, 0x84, 0x81, 4][..]
+        );
+        let checksums = &patch[patch.len() - 12..];
+        assert_eq!(checksums[..4], crc32fast::hash(&source).to_le_bytes());
+        assert_eq!(checksums[4..8], crc32fast::hash(&target).to_le_bytes());
+        assert_eq!(
+            checksums[8..],
+            crc32fast::hash(&patch[..patch.len() - 4]).to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_create_ips() {
+        let source = [0; 8];
+        let target = [0, 1, 2, 0, 0, 0, 3, 0];
+        assert_eq!(
+            create_ips(&source, &target),
+            Ok(b"PATCH\x00\x00\x01\x00\x02\x01\x02\x00\x00\x06\x00\x01\x03EOF".to_vec())
+        );
+        assert_eq!(
+            create_ips(&source, &source[..6]),
+            Ok(b"PATCHEOF\x00\x00\x06".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_create_ips_edge_offsets() {
+        let source = vec![0; IPS_MAX_OFFSET + 2];
+        let mut target = source.clone();
+        target[IPS_EOF_OFFSET] = 1;
+        let patch = create_ips(&source, &target).expect("The change is in the first 16 MiB");
+        // The record starts a byte early, so it isn't read as the end marker
+        assert_eq!(&patch[5..10], b"\x45\x4F\x45\x00\x02");
+
+        target[IPS_MAX_OFFSET + 1] = 1;
+        assert_eq!(
+            create_ips(&source, &target),
+            Err(IpsError::OffsetTooLarge(IPS_MAX_OFFSET + 1))
+        );
+    }
+
+    #[test]
+    fn test_bps_round_trip() -> Result<(), ApplyPatchError> {
+        let source: Vec<u8> = (0..=255).collect();
+        let mut target = source.clone();
+        target[3] = 0;
+        target[100..110].copy_from_slice(&[1; 10]);
+        target.extend_from_slice(&[7, 7]);
+        assert_eq!(apply_bps(&source, &create_bps(&source, &target))?, target);
+        let shorter = &source[..200];
+        assert_eq!(apply_bps(&source, &create_bps(&source, shorter))?, shorter);
+        Ok(())
+    }
+
+    // A patch like the ones other tools make, which copies from elsewhere in the source and target
+    #[test]
+    fn test_apply_bps_copies() -> Result<(), ApplyPatchError> {
+        let source = [1, 2, 3, 4];
+        let target = [3, 4, 3, 4, 3, 4];
+        let mut patch = vec![b'B', b'P', b'S', b'1', 0x84, 0x86, 0x80];
+        // Copy 2 bytes from 2 bytes further into the source, then 4 bytes from the start of the
+        // target
+        patch.extend_from_slice(&[0x86, 0x84, 0x8F, 0x80]);
+        patch.extend_from_slice(&crc32fast::hash(&source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(&target).to_le_bytes());
+        let patch_crc32 = crc32fast::hash(&patch);
+        patch.extend_from_slice(&patch_crc32.to_le_bytes());
+        assert_eq!(apply_bps(&source, &patch)?, target);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_bps_checks() {
+        let source = [0, 1, 2, 3];
+        let target = [0, 9, 2, 3];
+        let patch = create_bps(&source, &target);
+
+        let other_source = [0, 1, 2, 4];
+        assert!(matches!(
+            apply_bps(&other_source, &patch),
+            Err(ApplyPatchError::SourceChecksum { .. })
+        ));
+        assert_eq!(
+            apply_bps(&source[..3], &patch),
+            Err(ApplyPatchError::SourceSize {
+                expected: 4,
+                actual: 3
+            })
+        );
+
+        let mut damaged = patch.clone();
+        damaged[9] = 8;
+        assert!(matches!(
+            apply_bps(&source, &damaged),
+            Err(ApplyPatchError::PatchChecksum { .. })
+        ));
+
+        // A patch whose checksum was updated after its data changed still gives the wrong target
+        let len = damaged.len();
+        let patch_crc32 = crc32fast::hash(&damaged[..len - 4]);
+        damaged[len - 4..].copy_from_slice(&patch_crc32.to_le_bytes());
+        assert!(matches!(
+            apply_bps(&source, &damaged),
+            Err(ApplyPatchError::TargetChecksum { .. })
+        ));
+
+        assert_eq!(
+            apply_bps(&source, b"PATCHEOF"),
+            Err(ApplyPatchError::UnknownFormat)
+        );
+        assert_eq!(apply_bps(&source, b"BPS1"), Err(ApplyPatchError::Truncated));
+    }
+
+    #[test]
+    fn test_ips_round_trip() -> Result<(), ApplyPatchError> {
+        let source: Vec<u8> = (0..=255).collect();
+        let mut target = source.clone();
+        target[3] = 0;
+        target[100..110].copy_from_slice(&[1; 10]);
+        target.extend_from_slice(&[7, 7]);
+        let patch = create_ips(&source, &target).expect("The changes are in the first 16 MiB");
+        assert_eq!(apply_ips(&source, &patch)?, target);
+        let shorter = &source[..200];
+        let patch = create_ips(&source, shorter).expect("The size fits in 3 bytes");
+        assert_eq!(apply_ips(&source, &patch)?, shorter);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_ips_run_length() -> Result<(), ApplyPatchError> {
+        // Writes 5 bytes of 0xAA at offset 2
+        let patch = b"PATCH\x00\x00\x02\x00\x00\x00\x05\xAAEOF";
+        assert_eq!(
+            apply_ips(&[0; 8], patch)?,
+            vec![0, 0, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0]
+        );
+        assert_eq!(
+            apply_ips(&[0; 8], b"PATCH\x00\x00\x02\x00"),
+            Err(ApplyPatchError::Truncated)
+        );
+        Ok(())
+    }
+}
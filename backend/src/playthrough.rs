@@ -0,0 +1,178 @@
+use crate::{ability::Ability, graph::Graph};
+use serde::Serialize;
+use std::{fmt, fmt::Display, hash::Hash};
+
+#[derive(Serialize)]
+pub struct PlaythroughSphere {
+    // Abilities Kirby picks up in this sphere for the first time
+    pub abilities: Vec<Ability>,
+    pub nodes: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct GoalPath {
+    pub goal: String,
+    // Every node from the start to the goal, or None if the goal can't be reached
+    pub path: Option<Vec<String>>,
+}
+
+// How a seed is meant to be beaten. This uses the same reachability as the beatability check, so
+// any goal the randomizer considers reachable has a path here.
+#[derive(Serialize)]
+pub struct Playthrough {
+    pub spheres: Vec<PlaythroughSphere>,
+    pub goal_paths: Vec<GoalPath>,
+}
+
+impl Playthrough {
+    pub fn new<N, E>(graph: &impl Graph<N, E>) -> Self
+    where
+        N: Display + Clone + Eq + Hash,
+    {
+        let reachability = graph.get_reachability(graph.start());
+        let spheres = reachability
+            .spheres
+            .iter()
+            .map(|sphere| {
+                let mut abilities = sphere.abilities.clone();
+                abilities.sort();
+                let mut nodes: Vec<String> =
+                    sphere.nodes.iter().map(|node| node.to_string()).collect();
+                nodes.sort();
+                PlaythroughSphere { abilities, nodes }
+            })
+            .collect();
+        let goal_paths = graph
+            .goals()
+            .iter()
+            .map(|goal| GoalPath {
+                goal: goal.to_string(),
+                path: reachability
+                    .path_to(goal)
+                    .map(|path| path.iter().map(|node| node.to_string()).collect()),
+            })
+            .collect();
+        Self {
+            spheres,
+            goal_paths,
+        }
+    }
+}
+
+impl fmt::Display for Playthrough {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Playthrough:")?;
+        for (idx, sphere) in self.spheres.iter().enumerate() {
+            writeln!(f, "    Sphere {}:", idx)?;
+            if !sphere.abilities.is_empty() {
+                let abilities: Vec<String> = sphere
+                    .abilities
+                    .iter()
+                    .map(|ability| format!("{:?}", ability))
+                    .collect();
+                writeln!(f, "        Abilities: {}", abilities.join(", "))?;
+            }
+            for node in &sphere.nodes {
+                writeln!(f, "        {}", node)?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Paths to goals:")?;
+        for goal_path in &self.goal_paths {
+            writeln!(f, "    {}:", goal_path.goal)?;
+            match &goal_path.path {
+                Some(path) => {
+                    for node in path {
+                        writeln!(f, "        {}", node)?;
+                    }
+                }
+                None => writeln!(f, "        Unreachable")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ability::{AbilitySource, Requirement},
+        game_graph::{DynamicEdge, GameGraph, GraphData, StaticEdge},
+    };
+    use std::collections::HashMap;
+
+    fn static_edge(start: &str, end: &str, requires: Option<Requirement>) -> StaticEdge<String> {
+        StaticEdge {
+            start: start.to_string(),
+            end: end.to_string(),
+            two_way: false,
+            requires,
+        }
+    }
+
+    // a -> b -> c -> goal, plus a shortcut a -> goal that needs Cutter, which is found at b
+    fn test_graph() -> GameGraph {
+        GameGraph::new(GraphData {
+            version: 1,
+            door_data: HashMap::new(),
+            ability_sources: vec![AbilitySource {
+                node: "b".to_string(),
+                ability: Ability::Cutter,
+                addresses: vec![],
+            }],
+            ability_ids: HashMap::new(),
+            chests: vec![],
+            enemy_slots: vec![],
+            boss_rooms: vec![],
+            music_slots: vec![],
+            kirby_palettes: vec![],
+            start: "a".to_string(),
+            goals: vec!["goal".to_string(), "d".to_string()],
+            warps: vec![],
+            static_edges: vec![
+                static_edge("a", "b", None),
+                static_edge("b", "c", None),
+                static_edge("c", "goal", None),
+                static_edge("a", "goal", Some(Requirement::Ability(Ability::Cutter))),
+                static_edge("b", "e", Some(Requirement::Ability(Ability::Cutter))),
+            ],
+            dynamic_edges: vec![DynamicEdge {
+                start: "x".to_string(),
+                end: "d".to_string(),
+                two_way: false,
+                requires: None,
+                area_connection: false,
+                excluded: false,
+            }],
+        })
+    }
+
+    #[test]
+    fn test_playthrough_spheres() {
+        let playthrough = Playthrough::new(&test_graph());
+        assert_eq!(playthrough.spheres.len(), 2);
+        assert_eq!(playthrough.spheres[0].abilities, vec![Ability::Cutter]);
+        assert_eq!(playthrough.spheres[0].nodes, vec!["a", "b", "c", "goal"]);
+        assert!(playthrough.spheres[1].abilities.is_empty());
+        assert_eq!(playthrough.spheres[1].nodes, vec!["e"]);
+    }
+
+    #[test]
+    fn test_playthrough_goal_paths() {
+        let playthrough = Playthrough::new(&test_graph());
+        assert_eq!(playthrough.goal_paths[0].goal, "goal");
+        assert_eq!(
+            playthrough.goal_paths[0].path,
+            Some(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string(),
+                "goal".to_string()
+            ])
+        );
+        assert_eq!(playthrough.goal_paths[1].goal, "d");
+        assert_eq!(playthrough.goal_paths[1].path, None);
+    }
+}
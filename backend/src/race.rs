@@ -0,0 +1,138 @@
+use crate::config::Config;
+use sha2::{Digest, Sha256};
+use std::env;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RaceError {
+    #[error("Race mode is not enabled on this server")]
+    Disabled,
+    #[error("Wrong race unlock token")]
+    WrongToken,
+    #[error("The spoiler for this race is still locked")]
+    Locked,
+}
+
+// Secrets that only the server knows. The salt must stay the same for as long as a race seed is
+// being downloaded, or racers end up with different ROMs.
+pub struct RaceSecrets {
+    salt: String,
+    unlock_token: String,
+}
+
+impl RaceSecrets {
+    pub fn new(salt: String, unlock_token: String) -> Self {
+        Self { salt, unlock_token }
+    }
+
+    // Race mode is only enabled when both KATAM_RACE_SALT and KATAM_RACE_UNLOCK_TOKEN are set
+    pub fn from_env() -> Option<Self> {
+        let salt = env::var("KATAM_RACE_SALT").ok()?;
+        let unlock_token = env::var("KATAM_RACE_UNLOCK_TOKEN").ok()?;
+        Some(Self::new(salt, unlock_token))
+    }
+
+    pub fn check_unlock_token(&self, token: &str) -> Result<(), RaceError> {
+        // Compare every byte, so the time taken doesn't give away how much of the token matched
+        let matches = token.len() == self.unlock_token.len()
+            && token
+                .bytes()
+                .zip(self.unlock_token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+        if matches {
+            Ok(())
+        } else {
+            Err(RaceError::WrongToken)
+        }
+    }
+}
+
+fn hash_u64(bytes: &[&[u8]]) -> u64 {
+    let mut hasher = Sha256::new();
+    for part in bytes {
+        hasher.update(part);
+    }
+    let digest = hasher.finalize();
+    let mut first_bytes = [0; 8];
+    first_bytes.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(first_bytes)
+}
+
+// The seed the RNG is started with. Race seeds hash in the secret salt, so the public seed number
+// alone can't be used to generate the layout.
+pub fn rng_seed(config: &Config, secrets: Option<&RaceSecrets>) -> Result<u64, RaceError> {
+    if !config.race {
+        return Ok(config.seed);
+    }
+    let secrets = secrets.ok_or(RaceError::Disabled)?;
+    Ok(hash_u64(&[
+        secrets.salt.as_bytes(),
+        &config.seed.to_le_bytes(),
+    ]))
+}
+
+// Identifies a race by its settings, so the organizer can unlock it with the settings string
+// they shared
+pub fn race_id(config: &Config) -> String {
+    format!(
+        "{:016x}",
+        hash_u64(&[config.to_settings_string().as_bytes()])
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn race_config(seed: u64) -> Config {
+        Config {
+            seed,
+            race: true,
+            ..Config::default()
+        }
+    }
+
+    fn secrets(salt: &str) -> RaceSecrets {
+        RaceSecrets::new(salt.to_string(), "token".to_string())
+    }
+
+    #[test]
+    fn test_rng_seed() -> Result<(), RaceError> {
+        let config = race_config(42);
+        let seed = rng_seed(&config, Some(&secrets("salt")))?;
+        assert_ne!(seed, 42);
+        assert_eq!(seed, rng_seed(&config, Some(&secrets("salt")))?);
+        assert_ne!(seed, rng_seed(&config, Some(&secrets("pepper")))?);
+        assert_ne!(seed, rng_seed(&race_config(43), Some(&secrets("salt")))?);
+        assert!(matches!(rng_seed(&config, None), Err(RaceError::Disabled)));
+
+        let public = Config {
+            race: false,
+            ..race_config(42)
+        };
+        assert_eq!(rng_seed(&public, None)?, 42);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_unlock_token() {
+        let secrets = secrets("salt");
+        assert!(secrets.check_unlock_token("token").is_ok());
+        assert!(matches!(
+            secrets.check_unlock_token("tokem"),
+            Err(RaceError::WrongToken)
+        ));
+        assert!(matches!(
+            secrets.check_unlock_token("tok"),
+            Err(RaceError::WrongToken)
+        ));
+    }
+
+    #[test]
+    fn test_race_id() {
+        assert_eq!(race_id(&race_config(42)), race_id(&race_config(42)));
+        assert_ne!(race_id(&race_config(42)), race_id(&race_config(43)));
+        assert_eq!(race_id(&race_config(42)).len(), 16);
+    }
+}
@@ -1,128 +1,2461 @@
 use crate::{
-    config::{self, EntranceShuffleType},
-    graph::{DoorData, Graph},
-    rng::{ChooseMultipleFill, RandomBool},
-    rom::Rom,
+    ability::{Ability, Requirement},
+    boss::{Boss, BossKind},
+    config::{self, AbilityShuffleType, EnemyShuffleType, EntranceShuffleType, MusicShuffleType},
+    enemy::{Enemy, EnemyClass},
+    graph::{
+        AbilitySourceData, BossData, ChestData, DoorData, EdgeSwapError, EnemyData,
+        ExcludeEdgeError, GetEdgeEndpointsError, Graph, MusicData, PaletteData, PinEdgeError,
+        SetBossError,
+    },
+    item::Item,
+    music::Track,
+    palette::{Kirby, KirbyColor, Rgb},
+    playthrough::Playthrough,
+    rng::ChooseMultipleFill,
+    rom::{Rom, WriteDataError},
+    seed_hash::SeedHash,
+    spoiler::{ShuffledContents, Spoiler},
+};
+use serde::Serialize;
+use std::{
+    cmp::Eq,
+    collections::{HashMap, HashSet},
+    fmt,
+    fmt::{Debug, Display},
+    hash::Hash,
+    str::FromStr,
 };
-use std::{cmp::Eq, fmt::Debug, hash::Hash};
 use thiserror::Error;
 
+// TODO: Make this configurable
+const MAX_FILL_ATTEMPTS: usize = 1000;
+
 #[derive(Error, Debug)]
 pub enum KatamRandoError {
     #[error(transparent)]
     RomIO(#[from] std::io::Error),
+    #[error(transparent)]
+    EdgeSwap(#[from] EdgeSwapError),
+    #[error(transparent)]
+    EdgeEndpoints(#[from] GetEdgeEndpointsError),
+    #[error("The game can't be beaten before shuffling, so the game data is broken: {0}")]
+    UnbeatableGameData(String),
+    #[error("Failed to place edges in a beatable layout: {0}")]
+    NoBeatableLayout(ShuffleStats),
+    #[error("Invalid exclusion: {0}")]
+    Exclusion(#[from] ExcludeEdgeError),
+    #[error("Invalid plando: {0}")]
+    Plando(#[from] PinEdgeError),
+    #[error("Invalid plando: connecting {0} to {1} leaves Kirby stuck behind {1}")]
+    PlandoSoftLock(String, String),
+    #[error("Failed to find a beatable layout around the plando connections {0}: {1}")]
+    UnbeatablePlando(String, ShuffleStats),
+    #[error("Failed to give the ability sources abilities that keep the seed beatable")]
+    NoBeatableAbilities,
+    #[error("Failed to place the bosses in a beatable layout")]
+    NoBeatableBosses,
+    #[error("{0} leads into a boss room, so it can't be excluded or pinned while the bosses are shuffled")]
+    FixedBossDoor(String),
+    #[error("Failed to place a boss: {0}")]
+    BossPlacement(#[from] SetBossError),
+    #[error("Failed to write the randomized ROM: {0}")]
+    WriteData(#[from] WriteDataError),
+    #[error("The game data is missing {0}, so {1} can't be turned on")]
+    MissingGameData(&'static str, &'static str),
 }
 
 pub type Result<T> = std::result::Result<T, KatamRandoError>;
 
-pub fn randomize_katam<N: Debug + Eq + Hash, E, G: Graph<N, E> + DoorData<N>>(
+// Why a shuffled layout was thrown away
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Serialize)]
+pub enum RejectionReason {
+    UnreachableGoal,
+    SoftLock,
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RejectionReason::UnreachableGoal => write!(f, "unreachable goal"),
+            RejectionReason::SoftLock => write!(f, "soft-lock"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ShuffleStats {
+    pub attempts: usize,
+    // A layout can be rejected for more than one reason at once
+    pub rejections: HashMap<RejectionReason, usize>,
+}
+
+impl ShuffleStats {
+    fn reject(&mut self, reason: RejectionReason) {
+        *self.rejections.entry(reason).or_insert(0) += 1;
+    }
+}
+
+impl fmt::Display for ShuffleStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} shuffle attempt(s)", self.attempts)?;
+        let mut rejections: Vec<(String, usize)> = self
+            .rejections
+            .iter()
+            .map(|(reason, count)| (reason.to_string(), *count))
+            .collect();
+        rejections.sort();
+        for (reason, count) in rejections {
+            write!(f, ", {} rejected for {}", count, reason)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn randomize_katam<N, E, G>(
     config: config::Config,
-    mut rng: impl RandomBool + ChooseMultipleFill,
+    mut rng: impl ChooseMultipleFill,
+    // Only used for cosmetics, so they never change what `rng` draws
+    mut music_rng: impl ChooseMultipleFill,
+    mut palette_rng: impl ChooseMultipleFill,
     mut rom: impl Rom,
     graph: &mut G,
-) -> Result<()> {
-    match config.entrance_shuffle {
-        EntranceShuffleType::Standard => standard_shuffle(graph, &mut rng),
-        EntranceShuffleType::Chaos => chaos_shuffle(graph, &mut rng),
-    };
+) -> Result<Spoiler>
+where
+    N: Debug + Display + Clone + Eq + Hash + FromStr,
+    E: Copy + Eq + Hash,
+    G: Graph<N, E>
+        + DoorData<N>
+        + ChestData<N>
+        + AbilitySourceData<N>
+        + EnemyData<N>
+        + BossData<N>
+        + MusicData
+        + PaletteData
+        + Clone,
+{
+    // Without the data an option needs, it would quietly leave the game as it is
+    if config.shuffle_chests && graph.chests().is_empty() {
+        return Err(KatamRandoError::MissingGameData("chests", "chest shuffle"));
+    }
+    if config.ability_shuffle != AbilityShuffleType::Off {
+        let sources = graph.ability_sources();
+        if sources.is_empty() || sources.iter().any(|source| source.addresses.is_empty()) {
+            return Err(KatamRandoError::MissingGameData(
+                "ability source addresses",
+                "ability shuffle",
+            ));
+        }
+        if Ability::ALL
+            .iter()
+            .any(|ability| !graph.ability_ids().contains_key(ability))
+        {
+            return Err(KatamRandoError::MissingGameData(
+                "ability IDs",
+                "ability shuffle",
+            ));
+        }
+    }
+    if config.enemy_shuffle != EnemyShuffleType::Off && graph.enemy_slots().is_empty() {
+        return Err(KatamRandoError::MissingGameData(
+            "enemy slots",
+            "enemy shuffle",
+        ));
+    }
+    if config.music_shuffle != MusicShuffleType::Off && graph.music_slots().is_empty() {
+        return Err(KatamRandoError::MissingGameData(
+            "music slots",
+            "music shuffle",
+        ));
+    }
+    if Kirby::ALL
+        .iter()
+        .zip(&config.kirby_colors)
+        .any(|(kirby, color)| {
+            *color != KirbyColor::Vanilla
+                && !graph
+                    .kirby_palettes()
+                    .iter()
+                    .any(|palette| palette.kirby == *kirby)
+        })
+    {
+        return Err(KatamRandoError::MissingGameData(
+            "Kirby palettes",
+            "Kirby colors",
+        ));
+    }
+    let fixed = FixedEdges::parse(&config)?;
+    if config.shuffle_bosses {
+        // The boss shuffle takes these doors out of the door shuffle
+        let boss_nodes: HashSet<&N> = graph
+            .boss_rooms()
+            .iter()
+            .flat_map(|room| {
+                room.doors
+                    .iter()
+                    .chain(std::iter::once(&room.boss.entrance))
+            })
+            .collect();
+        let mut fixed_nodes = fixed.excluded.iter().chain(
+            fixed
+                .pinned
+                .iter()
+                .flat_map(|(door, entrance)| vec![door, entrance]),
+        );
+        if let Some(node) = fixed_nodes.find(|node| boss_nodes.contains(node)) {
+            return Err(KatamRandoError::FixedBossDoor(node.to_string()));
+        }
+    }
+    // Abilities are placed first, so the door shuffle checks its layouts against them
+    match config.ability_shuffle {
+        AbilityShuffleType::Off => {}
+        AbilityShuffleType::SameTier => shuffle_abilities(graph, &mut rng, true)?,
+        AbilityShuffleType::Full => shuffle_abilities(graph, &mut rng, false)?,
+    }
+    if config.shuffle_bosses {
+        shuffle_bosses(graph, &mut rng)?;
+    }
+    let stats = match config.entrance_shuffle {
+        EntranceShuffleType::Standard => standard_shuffle(graph, &mut rng, &fixed),
+        EntranceShuffleType::Chaos => chaos_shuffle(graph, &mut rng, &fixed),
+        EntranceShuffleType::AreaRestricted => area_restricted_shuffle(graph, &mut rng, &fixed),
+        EntranceShuffleType::AreaConnections => area_connection_shuffle(graph, &mut rng, &fixed),
+    }
+    .map_err(|e| match e {
+        KatamRandoError::NoBeatableLayout(stats) if !fixed.pinned.is_empty() => {
+            KatamRandoError::UnbeatablePlando(describe_pins(&fixed.pinned), stats)
+        }
+        e => e,
+    })?;
+    if config.shuffle_chests {
+        shuffle_chests(graph, &mut rng);
+    }
+    match config.enemy_shuffle {
+        EnemyShuffleType::Off => {}
+        EnemyShuffleType::KeepAbilitySources => shuffle_enemies(graph, &mut rng, true),
+        EnemyShuffleType::Full => shuffle_enemies(graph, &mut rng, false),
+    }
+    match config.music_shuffle {
+        MusicShuffleType::Off => {}
+        MusicShuffleType::ExcludeJingles => shuffle_music(graph, &mut music_rng, true),
+        MusicShuffleType::Full => shuffle_music(graph, &mut music_rng, false),
+    }
+    color_kirbys(graph, &mut palette_rng, &config.kirby_colors);
     rom.write_data(graph)?;
-    Ok(())
+
+    let connections = graph.get_connections();
+    let mut placements: Vec<(String, String)> = connections
+        .iter()
+        .map(|connection| (connection.door.to_string(), connection.entrance.to_string()))
+        .collect();
+    placements.extend(
+        graph
+            .chests()
+            .iter()
+            .map(|chest| (chest.name.clone(), chest.item.name.clone())),
+    );
+    placements.extend(
+        graph
+            .enemy_slots()
+            .iter()
+            .map(|slot| (slot.name.clone(), slot.enemy.name.clone())),
+    );
+    placements.extend(
+        graph
+            .ability_sources()
+            .iter()
+            .map(|source| (source.node.to_string(), format!("{:?}", source.ability))),
+    );
+    let hash = SeedHash::new(&config, graph.version(), &placements);
+    let contents = ShuffledContents {
+        chests: if config.shuffle_chests {
+            graph.chests()
+        } else {
+            &[]
+        },
+        ability_sources: if config.ability_shuffle == AbilityShuffleType::Off {
+            &[]
+        } else {
+            graph.ability_sources()
+        },
+        enemy_slots: if config.enemy_shuffle == EnemyShuffleType::Off {
+            &[]
+        } else {
+            graph.enemy_slots()
+        },
+        boss_rooms: if config.shuffle_bosses {
+            graph.boss_rooms()
+        } else {
+            &[]
+        },
+        music_slots: if config.music_shuffle == MusicShuffleType::Off {
+            &[]
+        } else {
+            graph.music_slots()
+        },
+        kirby_palettes: graph.kirby_palettes(),
+    };
+    Ok(Spoiler::new(
+        config,
+        hash,
+        stats,
+        connections,
+        contents,
+        Playthrough::new(graph),
+    ))
+}
+
+// Gives every ability source a random ability, either from the same tier as its vanilla ability
+// or from all of them, until the game is beatable with the vanilla door layout
+fn shuffle_abilities<N, E, G, R>(graph: &mut G, rng: &mut R, same_tier: bool) -> Result<()>
+where
+    N: Clone + Eq + Hash,
+    E: Eq + Hash,
+    G: Graph<N, E> + AbilitySourceData<N>,
+    R: ChooseMultipleFill,
+{
+    let vanilla: Vec<Ability> = graph
+        .ability_sources()
+        .iter()
+        .map(|source| source.ability)
+        .collect();
+    for _ in 0..MAX_FILL_ATTEMPTS {
+        for (idx, vanilla_ability) in vanilla.iter().enumerate() {
+            let choices: Vec<Ability> = Ability::ALL
+                .iter()
+                .copied()
+                .filter(|ability| !same_tier || ability.tier() == vanilla_ability.tier())
+                .collect();
+            if let Some(ability) = choose_random(rng, &choices) {
+                graph.set_source_ability(idx, ability);
+            }
+        }
+        if is_beatable(graph) {
+            return Ok(());
+        }
+    }
+    for (idx, ability) in vanilla.into_iter().enumerate() {
+        graph.set_source_ability(idx, ability);
+    }
+    Err(KatamRandoError::NoBeatableAbilities)
+}
+
+// Shuffles the items of every chest Kirby can get to. None of the chest items are needed to get
+// anywhere, so the only logic needed is to keep items out of chests Kirby can't reach. Those
+// chests keep their vanilla item.
+fn shuffle_chests<N, E, G, R>(graph: &mut G, rng: &mut R)
+where
+    N: Clone + Eq + Hash,
+    G: Graph<N, E> + ChestData<N>,
+    R: ChooseMultipleFill,
+{
+    let reachability = graph.get_reachability(graph.start());
+    let reachable_chests: Vec<usize> = graph
+        .chests()
+        .iter()
+        .enumerate()
+        .filter(|(_, chest)| {
+            reachability.nodes.contains(&chest.node)
+                && chest
+                    .requires
+                    .as_ref()
+                    .is_none_or(|requires| requires.is_met(&reachability.abilities))
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+    let mut items: Vec<Item> = reachable_chests
+        .iter()
+        .map(|idx| graph.chests()[*idx].item.clone())
+        .collect();
+    shuffle(rng, &mut items);
+    for (idx, item) in reachable_chests.into_iter().zip(items) {
+        graph.set_chest_item(idx, item);
+    }
+}
+
+// Shuffles the bosses among the boss rooms of their kind, until every goal can be reached with the
+// vanilla door layout. The goals are the rooms of the bosses that drop mirror shards, so wherever
+// a boss ends up, Kirby can still get its shard.
+fn shuffle_bosses<N, E, G, R>(graph: &mut G, rng: &mut R) -> Result<()>
+where
+    N: Clone + Eq + Hash,
+    E: Eq + Hash,
+    G: Graph<N, E> + BossData<N>,
+    R: ChooseMultipleFill,
+{
+    graph.separate_boss_rooms();
+    let mut rooms_by_kind: HashMap<BossKind, Vec<usize>> = HashMap::new();
+    for (idx, room) in graph.boss_rooms().iter().enumerate() {
+        rooms_by_kind.entry(room.boss.kind).or_default().push(idx);
+    }
+    // Sorted so the same seed always gives the same bosses
+    let mut rooms_by_kind: Vec<Vec<usize>> = rooms_by_kind.into_values().collect();
+    rooms_by_kind.sort();
+
+    for _ in 0..MAX_FILL_ATTEMPTS {
+        for rooms in &rooms_by_kind {
+            let mut bosses: Vec<Boss<N>> = rooms
+                .iter()
+                .map(|idx| graph.boss_rooms()[*idx].boss.clone())
+                .collect();
+            shuffle(rng, &mut bosses);
+            for (idx, boss) in rooms.iter().zip(bosses) {
+                graph.set_room_boss(*idx, boss)?;
+            }
+        }
+        if is_beatable(graph) {
+            return Ok(());
+        }
+    }
+    Err(KatamRandoError::NoBeatableBosses)
+}
+
+// Shuffles the tracks among the music slots, so every track plays as often as it does in vanilla.
+// Jingles can be left out, in which case they stay where they are and never replace background
+// music.
+fn shuffle_music<G, R>(graph: &mut G, rng: &mut R, exclude_jingles: bool)
+where
+    G: MusicData,
+    R: ChooseMultipleFill,
+{
+    let shuffled_slots: Vec<usize> = graph
+        .music_slots()
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| !exclude_jingles || !slot.track.jingle)
+        .map(|(idx, _)| idx)
+        .collect();
+    let mut tracks: Vec<Track> = shuffled_slots
+        .iter()
+        .map(|idx| graph.music_slots()[*idx].track.clone())
+        .collect();
+    shuffle(rng, &mut tracks);
+    for (idx, track) in shuffled_slots.into_iter().zip(tracks) {
+        graph.set_slot_track(idx, track);
+    }
 }
 
-pub fn is_beatable<N: Debug, E>(graph: &impl Graph<N, E>) -> bool {
-    graph.get_unreachable_regions().len() == 1
+// Recolors each Kirby with its color from the settings, in the order of `Kirby::ALL`
+fn color_kirbys<G, R>(graph: &mut G, rng: &mut R, colors: &[KirbyColor; 4])
+where
+    G: PaletteData,
+    R: ChooseMultipleFill,
+{
+    let channels: Vec<u8> = (0..=u8::MAX).collect();
+    for (kirby, color) in Kirby::ALL.iter().zip(colors) {
+        let color = match color {
+            KirbyColor::Vanilla => continue,
+            KirbyColor::Random => {
+                let mut channel = || choose_random(rng, &channels).unwrap_or_default();
+                Rgb::new(channel(), channel(), channel())
+            }
+            KirbyColor::Preset(paint) => paint.color(),
+            KirbyColor::Custom(color) => *color,
+        };
+        graph.set_kirby_color(*kirby, color);
+    }
 }
 
-fn standard_shuffle<N: Debug, E, R>(graph: &mut impl Graph<N, E>, rng: &mut R)
+// Replaces the enemy in every slot with a random enemy of the same class, picked from the enemies
+// found anywhere in the game. Enemies don't block any edges, but they can be ability sources, so
+// slots at nodes with an ability source can be kept vanilla to keep those abilities in logic.
+fn shuffle_enemies<N, E, G, R>(graph: &mut G, rng: &mut R, keep_ability_sources: bool)
 where
-    R: RandomBool + ChooseMultipleFill,
+    N: Eq + Hash,
+    G: Graph<N, E> + EnemyData<N> + AbilitySourceData<N>,
+    R: ChooseMultipleFill,
 {
-    // TODO: this assumption should already be checked upon loading the graph data
+    let source_nodes: HashSet<&N> = graph
+        .ability_sources()
+        .iter()
+        .map(|source| &source.node)
+        .collect();
+    let shuffled_slots: Vec<usize> = graph
+        .enemy_slots()
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| !keep_ability_sources || !source_nodes.contains(&slot.node))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    // Listed in the order they are first found, so the same seed always picks the same enemies
+    let mut pools: HashMap<EnemyClass, Vec<Enemy>> = HashMap::new();
+    for slot in graph.enemy_slots() {
+        let pool = pools.entry(slot.enemy.class).or_default();
+        if !pool.iter().any(|enemy| enemy.name == slot.enemy.name) {
+            pool.push(slot.enemy.clone());
+        }
+    }
+
+    for idx in shuffled_slots {
+        let pool = &pools[&graph.enemy_slots()[idx].enemy.class];
+        let indices: Vec<usize> = (0..pool.len()).collect();
+        if let Some(choice) = choose_random(rng, &indices) {
+            graph.set_slot_enemy(idx, pool[choice].clone());
+        }
+    }
+}
+
+// Edges that are placed before the shuffle and left alone by it
+pub struct FixedEdges<N> {
+    // Doors that keep their vanilla entrance
+    pub excluded: Vec<N>,
+    // Doors and the entrances they are pinned to
+    pub pinned: Vec<(N, N)>,
+}
+
+impl<N> Default for FixedEdges<N> {
+    fn default() -> Self {
+        Self {
+            excluded: vec![],
+            pinned: vec![],
+        }
+    }
+}
+
+impl<N: FromStr> FixedEdges<N> {
+    fn parse(config: &config::Config) -> Result<Self> {
+        let excluded = config
+            .excluded_doors
+            .iter()
+            .map(|door| {
+                door.parse()
+                    .map_err(|_| ExcludeEdgeError::UnknownDoor(door.clone()).into())
+            })
+            .collect::<Result<Vec<N>>>()?;
+        let pinned = config
+            .plando
+            .connections
+            .iter()
+            .map(|connection| {
+                let door = connection
+                    .door
+                    .parse()
+                    .map_err(|_| PinEdgeError::UnknownDoor(connection.door.clone()))?;
+                let entrance = connection
+                    .entrance
+                    .parse()
+                    .map_err(|_| PinEdgeError::UnknownEntrance(connection.entrance.clone()))?;
+                Ok((door, entrance))
+            })
+            .collect::<Result<Vec<(N, N)>>>()?;
+        Ok(Self { excluded, pinned })
+    }
+}
+
+fn describe_pins<N: Debug>(pins: &[(N, N)]) -> String {
+    pins.iter()
+        .map(|(door, entrance)| format!("{:?} -> {:?}", door, entrance))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// Why a goal could not be reached from the start
+#[derive(Debug, PartialEq)]
+pub enum GoalFailure<N> {
+    // No path leads to the goal, whatever abilities Kirby has
+    NoPath,
+    // Every path to the goal goes through one of these edges, whose requirements can't be met
+    // with the abilities Kirby can pick up on the way
+    MissingAbilities(Vec<(N, N, Requirement)>),
+}
+
+#[derive(Debug)]
+pub struct BeatabilityReport<N> {
+    pub failed_goals: Vec<(N, GoalFailure<N>)>,
+}
+
+impl<N> BeatabilityReport<N> {
+    pub fn is_beatable(&self) -> bool {
+        self.failed_goals.is_empty()
+    }
+}
+
+pub fn check_beatability<N, E>(graph: &impl Graph<N, E>) -> BeatabilityReport<N>
+where
+    N: Clone + Eq + Hash,
+    E: Eq + Hash,
+{
+    let reachability = graph.get_reachability(graph.start());
+    let unreached_goals: Vec<&N> = graph
+        .goals()
+        .iter()
+        .filter(|goal| !reachability.nodes.contains(goal))
+        .collect();
+    if unreached_goals.is_empty() {
+        return BeatabilityReport {
+            failed_goals: vec![],
+        };
+    }
+
+    let no_edges = HashSet::new();
+    let reachable_with_any_abilities = graph.get_reachable_nodes(graph.start(), &no_edges);
+    let blocked_edges: Vec<_> = reachability
+        .blocked_edges
+        .iter()
+        .map(|blocked| (blocked, graph.get_reachable_nodes(&blocked.1, &no_edges)))
+        .collect();
+
+    let failed_goals = unreached_goals
+        .into_iter()
+        .map(|goal| {
+            let failure = if reachable_with_any_abilities.contains(goal) {
+                GoalFailure::MissingAbilities(
+                    blocked_edges
+                        .iter()
+                        .filter(|(_, reachable)| reachable.contains(goal))
+                        .map(|(blocked, _)| (*blocked).clone())
+                        .collect(),
+                )
+            } else {
+                GoalFailure::NoPath
+            };
+            (goal.clone(), failure)
+        })
+        .collect();
+
+    BeatabilityReport { failed_goals }
+}
+
+// Nodes Kirby can get to from the start but can't get back from, i.e. nodes that can't reach the
+// start, any goal or a warp. Only abilities that can be picked up from the start are assumed to
+// help.
+pub fn find_soft_locks<N, E>(graph: &impl Graph<N, E>) -> Vec<N>
+where
+    N: Clone + Eq + Hash,
+    E: Eq + Hash,
+{
+    let reachability = graph.get_reachability(graph.start());
+    let mut targets = vec![graph.start().clone()];
+    targets.extend(graph.goals().iter().cloned());
+    targets.extend(graph.warps().iter().cloned());
+    let escapable = graph.get_nodes_reaching(&targets, &reachability.abilities);
+    reachability
+        .nodes
+        .into_iter()
+        .filter(|node| !escapable.contains(node))
+        .collect()
+}
+
+pub fn is_beatable<N, E>(graph: &impl Graph<N, E>) -> bool
+where
+    N: Clone + Eq + Hash,
+    E: Eq + Hash,
+{
+    check_beatability(graph).is_beatable()
+}
+
+fn standard_shuffle<N, E, G, R>(
+    graph: &mut G,
+    rng: &mut R,
+    fixed: &FixedEdges<N>,
+) -> Result<ShuffleStats>
+where
+    N: Debug + Clone + Eq + Hash,
+    E: Copy + Eq + Hash,
+    G: Graph<N, E> + Clone,
+    R: ChooseMultipleFill,
+{
+    shuffle_edges(graph, rng, fixed)
+}
+
+fn chaos_shuffle<N, E, G, R>(
+    graph: &mut G,
+    rng: &mut R,
+    fixed: &FixedEdges<N>,
+) -> Result<ShuffleStats>
+where
+    N: Debug + Clone + Eq + Hash,
+    E: Copy + Eq + Hash,
+    G: Graph<N, E> + Clone,
+    R: ChooseMultipleFill,
+{
+    // Once every two-way door is split into a pair of one-way exits, any exit can be swapped
+    // with any other, so every door can lead to any entrance
+    graph.decouple_edges();
+    shuffle_edges(graph, rng, fixed)
+}
+
+fn area_restricted_shuffle<N, E, G, R>(
+    graph: &mut G,
+    rng: &mut R,
+    fixed: &FixedEdges<N>,
+) -> Result<ShuffleStats>
+where
+    N: Debug + Clone + Eq + Hash,
+    E: Copy + Eq + Hash,
+    G: Graph<N, E> + Clone,
+    R: ChooseMultipleFill,
+{
+    graph.restrict_edges_to_areas();
+    shuffle_edges(graph, rng, fixed)
+}
+
+fn area_connection_shuffle<N, E, G, R>(
+    graph: &mut G,
+    rng: &mut R,
+    fixed: &FixedEdges<N>,
+) -> Result<ShuffleStats>
+where
+    N: Debug + Clone + Eq + Hash,
+    E: Copy + Eq + Hash,
+    G: Graph<N, E> + Clone,
+    R: ChooseMultipleFill,
+{
+    graph.restrict_edges_to_area_connections();
+    shuffle_edges(graph, rng, fixed)
+}
+
+fn shuffle_edges<N, E, G, R>(
+    graph: &mut G,
+    rng: &mut R,
+    fixed: &FixedEdges<N>,
+) -> Result<ShuffleStats>
+where
+    N: Debug + Clone + Eq + Hash,
+    E: Copy + Eq + Hash,
+    G: Graph<N, E> + Clone,
+    R: ChooseMultipleFill,
+{
+    // The game data is checked when it is loaded, but the bosses are placed before this
+    let report = check_beatability(graph);
+    if !report.is_beatable() {
+        return Err(KatamRandoError::UnbeatableGameData(format!(
+            "{:?}",
+            report.failed_goals
+        )));
+    }
+    // Soft-locks in the unshuffled graph come from gaps in the door data rather than from the
+    // shuffle, so only new ones count against a layout
+    let known_soft_locks: HashSet<N> = find_soft_locks(graph).into_iter().collect();
+    fix_edges(graph, fixed, &known_soft_locks)?;
+
+    let fill_graph = FillGraph::new(graph)?;
+    let initial_graph = graph.clone();
+    let mut stats = ShuffleStats::default();
+    while stats.attempts < MAX_FILL_ATTEMPTS {
+        stats.attempts += 1;
+        fill_edges(graph, &fill_graph, rng)?;
+        let reasons = rejection_reasons(graph, &known_soft_locks);
+        if reasons.is_empty() {
+            return Ok(stats);
+        }
+        for reason in reasons {
+            stats.reject(reason);
+        }
+        *graph = initial_graph.clone();
+    }
+
+    Err(KatamRandoError::NoBeatableLayout(stats))
+}
+
+// Takes excluded doors out of the shuffle, then places the plando's connections and rejects any
+// that are bound to soft-lock Kirby, whatever the rest of the layout looks like
+fn fix_edges<N, E, G>(
+    graph: &mut G,
+    fixed: &FixedEdges<N>,
+    known_soft_locks: &HashSet<N>,
+) -> Result<()>
+where
+    N: Debug + Clone + Eq + Hash,
+    E: Copy + Eq + Hash,
+    G: Graph<N, E>,
+{
+    for door in &fixed.excluded {
+        graph.exclude_edge(door)?;
+    }
+    let pins = &fixed.pinned;
+    if pins.is_empty() {
+        return Ok(());
+    }
+    for (door, entrance) in pins {
+        graph.pin_edge(door, entrance)?;
+    }
+
+    // Kirby can leave through any door that is still unplaced, so those count as a way out
+    // along with the start, the goals and warps. Requirements are assumed to be met.
+    let mut targets = vec![graph.start().clone()];
+    targets.extend(graph.goals().iter().cloned());
+    targets.extend(graph.warps().iter().cloned());
+    for pool in graph.get_edge_pools() {
+        for edge in pool.edges {
+            let (start, end) = graph.get_edge_endpoints(edge)?;
+            targets.push(start);
+            if pool.two_way {
+                targets.push(end);
+            }
+        }
+    }
+    let escapable = graph.get_nodes_reaching(&targets, &Ability::ALL.iter().copied().collect());
+    match pins
+        .iter()
+        .find(|(_, entrance)| !escapable.contains(entrance) && !known_soft_locks.contains(entrance))
+    {
+        Some((door, entrance)) => Err(KatamRandoError::PlandoSoftLock(
+            format!("{:?}", door),
+            format!("{:?}", entrance),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn rejection_reasons<N, E>(
+    graph: &impl Graph<N, E>,
+    known_soft_locks: &HashSet<N>,
+) -> Vec<RejectionReason>
+where
+    N: Clone + Eq + Hash,
+    E: Eq + Hash,
+{
+    let mut reasons = vec![];
     if !is_beatable(graph) {
-        panic!(
-            "Initial graph unbeatable. Unreachable regions: {:?}",
-            graph.get_unreachable_regions()
-        );
+        reasons.push(RejectionReason::UnreachableGoal);
+    }
+    if find_soft_locks(graph)
+        .iter()
+        .any(|node| !known_soft_locks.contains(node))
+    {
+        reasons.push(RejectionReason::SoftLock);
+    }
+    reasons
+}
+
+// Which endpoint of an unplaced edge the fill reached it from
+#[derive(Copy, Clone, PartialEq)]
+enum Side {
+    Start,
+    End,
+}
+
+impl Side {
+    fn index(self) -> usize {
+        match self {
+            Side::Start => 0,
+            Side::End => 1,
+        }
     }
+}
 
-    // TODO: Make this configurable
-    let iterations = 100;
+// An edge that has not been placed by the fill yet. Nodes are referred to by their index in the
+// fill's node list so that the main loop doesn't have to hash node IDs.
+#[derive(Clone)]
+struct Slot<E> {
+    edge: E,
+    pool: usize,
+    two_way: bool,
+    start: usize,
+    end: usize,
+}
 
-    for _ in 0..iterations {
-        if let Some((edge1, edge2)) = graph.pick_random_edges(rng) {
-            let (new_edge1, new_edge2) = graph
-                .swap_edges(edge1, edge2)
-                .expect("Standard shuffle: Swapping edges failed");
+// The parts of the graph the fill needs. The graph is the same at the start of every attempt, so
+// this is only built once per shuffle.
+struct FillGraph<N, E> {
+    nodes: Vec<N>,
+    slots: Vec<Slot<E>>,
+    pool_count: usize,
+    // Edges the shuffle leaves alone, by start node
+    fixed_edges: Vec<Vec<usize>>,
+    // Slots by start node. The start of a slot never changes.
+    slot_starts: Vec<Vec<usize>>,
+    // The start, the goals and the warps, which Kirby can always get out of
+    exits: Vec<bool>,
+    // Nodes that can't get to an exit or an unplaced door before anything is placed. These come
+    // from gaps in the game data, so a door leading to one can't be helped.
+    stuck: Vec<bool>,
+    root: usize,
+}
 
-            if !is_beatable(graph) {
-                graph
-                    .swap_edges(new_edge1, new_edge2)
-                    .expect("Standard shuffle: Swapping back edges failed");
+impl<N: Clone + Eq + Hash, E: Copy + Eq + Hash> FillGraph<N, E> {
+    fn new(graph: &impl Graph<N, E>) -> Result<Self> {
+        let mut nodes: Vec<N> = vec![];
+        let mut node_indices: HashMap<N, usize> = HashMap::new();
+        let mut node_index = |node: N| {
+            *node_indices.entry(node.clone()).or_insert_with(|| {
+                nodes.push(node);
+                nodes.len() - 1
+            })
+        };
+
+        let root = node_index(graph.start().clone());
+        let exit_nodes: Vec<usize> = graph
+            .goals()
+            .iter()
+            .chain(graph.warps())
+            .map(|node| node_index(node.clone()))
+            .chain(std::iter::once(root))
+            .collect();
+        let pools = graph.get_edge_pools();
+        let pool_count = pools.len();
+        let mut slots: Vec<Slot<E>> = vec![];
+        for (pool_idx, pool) in pools.into_iter().enumerate() {
+            for edge in pool.edges {
+                let (start, end) = graph.get_edge_endpoints(edge)?;
+                slots.push(Slot {
+                    edge,
+                    pool: pool_idx,
+                    two_way: pool.two_way,
+                    start: node_index(start),
+                    end: node_index(end),
+                });
+            }
+        }
+        let fixed: Vec<(usize, usize)> = graph
+            .get_fixed_edges()
+            .into_iter()
+            .map(|(start, end)| (node_index(start), node_index(end)))
+            .collect();
+
+        let mut fixed_edges = vec![vec![]; nodes.len()];
+        let mut incoming = vec![vec![]; nodes.len()];
+        for (start, end) in fixed {
+            fixed_edges[start].push(end);
+            incoming[end].push(start);
+        }
+        let mut slot_starts = vec![vec![]; nodes.len()];
+        let mut exits = vec![false; nodes.len()];
+        let mut stack = exit_nodes;
+        for node in &stack {
+            exits[*node] = true;
+        }
+        for (slot_idx, slot) in slots.iter().enumerate() {
+            slot_starts[slot.start].push(slot_idx);
+            stack.push(slot.start);
+            if slot.two_way {
+                stack.push(slot.end);
+            }
+        }
+        let mut stuck = vec![true; nodes.len()];
+        while let Some(node) = stack.pop() {
+            if stuck[node] {
+                stuck[node] = false;
+                stack.extend(incoming[node].iter().copied());
             }
         }
+
+        Ok(Self {
+            nodes,
+            slots,
+            pool_count,
+            fixed_edges,
+            slot_starts,
+            exits,
+            stuck,
+            root,
+        })
     }
 }
 
-fn chaos_shuffle<N: Debug, E, R>(_graph: &mut impl Graph<N, E>, _rng: &mut R)
+// A list that an item can be taken out of in constant time, by swapping the last item into its
+// place
+struct IndexedList {
+    items: Vec<usize>,
+    // Where each item is in `items`
+    positions: Vec<usize>,
+}
+
+impl IndexedList {
+    fn new(items: Vec<usize>, capacity: usize) -> Self {
+        let mut positions = vec![0; capacity];
+        for (position, item) in items.iter().enumerate() {
+            positions[*item] = position;
+        }
+        Self { items, positions }
+    }
+
+    fn remove(&mut self, item: usize) {
+        let position = self.positions[item];
+        self.items.swap_remove(position);
+        if let Some(moved) = self.items.get(position) {
+            self.positions[*moved] = position;
+        }
+    }
+
+    fn swap(&mut self, position1: usize, position2: usize) {
+        self.items.swap(position1, position2);
+        self.positions[self.items[position1]] = position1;
+        self.positions[self.items[position2]] = position2;
+    }
+}
+
+// Everything the fill keeps track of while placing edges, updated as each edge is placed
+struct Fill<'a, N, E> {
+    graph: &'a FillGraph<N, E>,
+    slots: Vec<Slot<E>>,
+    placed: Vec<bool>,
+    unplaced: IndexedList,
+    pools: Vec<IndexedList>,
+    placed_by_pool: Vec<Vec<usize>>,
+    // Placed edges, by start node
+    placed_edges: Vec<Vec<usize>>,
+    // Two-way slots that had their end at each node at some point. The end of a slot changes when
+    // it is swapped, so these are checked against the slot before being used.
+    slot_ends: Vec<Vec<usize>>,
+    // Unplaced doors at each node, i.e. starts of unplaced slots and ends of unplaced two-way
+    // slots. Kirby can leave through any of these, wherever they end up leading.
+    open_doors: Vec<usize>,
+    // Unplaced doors at nodes that haven't been reached yet
+    unreached_doors: usize,
+    reached: Vec<bool>,
+    // Sides of unplaced slots at reached nodes. Placing an edge can leave stale entries behind,
+    // which are dropped when they are picked.
+    frontier: Vec<(usize, Side)>,
+    in_frontier: Vec<[bool; 2]>,
+    // Scratch space for searches, so they don't allocate. A node has been visited in the current
+    // search if its entry matches the search count.
+    visits: Vec<usize>,
+    search: usize,
+    stack: Vec<usize>,
+}
+
+impl<'a, N, E: Copy> Fill<'a, N, E> {
+    fn new(graph: &'a FillGraph<N, E>) -> Self {
+        let node_count = graph.nodes.len();
+        let slot_count = graph.slots.len();
+        let mut pools = vec![vec![]; graph.pool_count];
+        let mut open_doors = vec![0; node_count];
+        let mut slot_ends = vec![vec![]; node_count];
+        for (slot_idx, slot) in graph.slots.iter().enumerate() {
+            pools[slot.pool].push(slot_idx);
+            open_doors[slot.start] += 1;
+            if slot.two_way {
+                open_doors[slot.end] += 1;
+                slot_ends[slot.end].push(slot_idx);
+            }
+        }
+        let mut fill = Self {
+            graph,
+            slots: graph.slots.clone(),
+            placed: vec![false; slot_count],
+            unplaced: IndexedList::new((0..slot_count).collect(), slot_count),
+            pools: pools
+                .into_iter()
+                .map(|pool| IndexedList::new(pool, slot_count))
+                .collect(),
+            placed_by_pool: vec![vec![]; graph.pool_count],
+            placed_edges: vec![vec![]; node_count],
+            slot_ends,
+            unreached_doors: open_doors.iter().sum(),
+            open_doors,
+            reached: vec![false; node_count],
+            frontier: vec![],
+            in_frontier: vec![[false; 2]; slot_count],
+            visits: vec![0; node_count],
+            search: 0,
+            stack: vec![],
+        };
+        fill.reach(graph.root);
+        fill
+    }
+
+    fn add_door(&mut self, node: usize) {
+        self.open_doors[node] += 1;
+        if !self.reached[node] {
+            self.unreached_doors += 1;
+        }
+    }
+
+    fn remove_door(&mut self, node: usize) {
+        self.open_doors[node] -= 1;
+        if !self.reached[node] {
+            self.unreached_doors -= 1;
+        }
+    }
+
+    fn add_to_frontier(&mut self, slot_idx: usize, side: Side) {
+        if !self.in_frontier[slot_idx][side.index()] {
+            self.in_frontier[slot_idx][side.index()] = true;
+            self.frontier.push((slot_idx, side));
+        }
+    }
+
+    // Marks everything Kirby can get to from `node` as reached, using fixed and placed edges
+    fn reach(&mut self, node: usize) {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            if self.reached[node] {
+                continue;
+            }
+            self.reached[node] = true;
+            self.unreached_doors -= self.open_doors[node];
+            for slot_idx in &self.graph.slot_starts[node] {
+                if !self.placed[*slot_idx] {
+                    self.add_to_frontier(*slot_idx, Side::Start);
+                }
+            }
+            for idx in 0..self.slot_ends[node].len() {
+                let slot_idx = self.slot_ends[node][idx];
+                if !self.placed[slot_idx] && self.slots[slot_idx].end == node {
+                    self.add_to_frontier(slot_idx, Side::End);
+                }
+            }
+            stack.extend(self.graph.fixed_edges[node].iter().copied());
+            stack.extend(self.placed_edges[node].iter().copied());
+        }
+    }
+
+    // Searches the fixed and placed edges from `start`, skipping nodes `skip` returns true for,
+    // until a node `found` returns true for is reached
+    fn search(
+        &mut self,
+        start: usize,
+        skip: impl Fn(&Self, usize) -> bool,
+        found: impl Fn(&Self, usize) -> bool,
+    ) -> bool {
+        self.search += 1;
+        let mut stack = std::mem::take(&mut self.stack);
+        stack.clear();
+        stack.push(start);
+        let mut result = false;
+        while let Some(node) = stack.pop() {
+            if self.visits[node] == self.search || skip(self, node) {
+                continue;
+            }
+            if found(self, node) {
+                result = true;
+                break;
+            }
+            self.visits[node] = self.search;
+            stack.extend(self.graph.fixed_edges[node].iter().copied());
+            stack.extend(self.placed_edges[node].iter().copied());
+        }
+        self.stack = stack;
+        result
+    }
+
+    // Whether placing an edge at `new_node` would get Kirby to unplaced doors that haven't been
+    // reached yet
+    fn opens_doors(&mut self, new_node: usize) -> bool {
+        self.search(
+            new_node,
+            |fill, node| fill.reached[node],
+            |fill, node| fill.open_doors[node] > 0,
+        )
+    }
+
+    // Connects the start of `from` to the end of `to`. `to` takes over the end of `from`, so the
+    // ends of the unplaced edges stay the same.
+    fn connect(&mut self, from_idx: usize, to_idx: usize) {
+        let (start, end) = (self.slots[from_idx].start, self.slots[to_idx].end);
+        self.remove_door(start);
+        self.placed_edges[start].push(end);
+        if self.slots[from_idx].two_way {
+            self.remove_door(end);
+            self.placed_edges[end].push(start);
+        }
+    }
+
+    fn disconnect(&mut self, from_idx: usize, to_idx: usize) {
+        let (start, end) = (self.slots[from_idx].start, self.slots[to_idx].end);
+        self.add_door(start);
+        self.placed_edges[start].pop();
+        if self.slots[from_idx].two_way {
+            self.add_door(end);
+            self.placed_edges[end].pop();
+        }
+    }
+
+    // Whether Kirby could still get out after `from` is connected to the end of `to`, either
+    // through an exit or through a door that hasn't been placed yet. Checking this for every edge
+    // means nothing is left without a way out once every edge is placed. An entrance that was stuck
+    // to begin with can't be helped, so for those only the door leading there has to have another
+    // way out. Requirements are assumed to be met.
+    fn can_leave(&mut self, from_idx: usize, to_idx: usize) -> bool {
+        self.connect(from_idx, to_idx);
+        let can_leave = self.can_get_out(self.slots[from_idx].start, self.slots[to_idx].end);
+        self.disconnect(from_idx, to_idx);
+        can_leave
+    }
+
+    fn can_get_out(&mut self, start: usize, end: usize) -> bool {
+        let node = if self.graph.stuck[end] { start } else { end };
+        self.search(
+            node,
+            |_, _| false,
+            |fill, node| fill.graph.exits[node] || fill.open_doors[node] > 0,
+        )
+    }
+
+    // Points the door of each placed slot at the entrance of the other
+    fn exchange_ends(&mut self, slot_idx: usize, other_idx: usize) {
+        let (start, end) = (self.slots[slot_idx].start, self.slots[slot_idx].end);
+        let (other_start, other_end) = (self.slots[other_idx].start, self.slots[other_idx].end);
+        self.replace_placed_edge(start, end, other_end);
+        self.replace_placed_edge(other_start, other_end, end);
+        if self.slots[slot_idx].two_way {
+            self.replace_placed_edge(end, start, other_start);
+            self.replace_placed_edge(other_end, other_start, start);
+        }
+        self.slots[slot_idx].end = other_end;
+        self.slots[other_idx].end = end;
+    }
+
+    fn replace_placed_edge(&mut self, start: usize, old_end: usize, new_end: usize) {
+        if let Some(end) = self.placed_edges[start]
+            .iter_mut()
+            .find(|end| **end == old_end)
+        {
+            *end = new_end;
+        }
+    }
+
+    // Looks for an edge placed earlier in the pool of `slot` that it can trade entrances with,
+    // so that Kirby can get out of both. This is how the last edges of a pool get placed when
+    // every candidate left would be a dead end.
+    fn find_trade<R: ChooseMultipleFill>(&mut self, rng: &mut R, slot_idx: usize) -> Option<usize> {
+        let pool = self.slots[slot_idx].pool;
+        let len = self.placed_by_pool[pool].len();
+        for position in 0..len {
+            let other = position + random_index(rng, len - position);
+            self.placed_by_pool[pool].swap(position, other);
+            let other_idx = self.placed_by_pool[pool][position];
+            if other_idx == slot_idx {
+                continue;
+            }
+            self.exchange_ends(slot_idx, other_idx);
+            let (start, end) = (self.slots[slot_idx].start, self.slots[slot_idx].end);
+            let (other_start, other_end) = (self.slots[other_idx].start, self.slots[other_idx].end);
+            if self.can_get_out(start, end) && self.can_get_out(other_start, other_end) {
+                return Some(other_idx);
+            }
+            self.exchange_ends(slot_idx, other_idx);
+        }
+        None
+    }
+
+    // Marks whatever a placed slot newly connects as reached
+    fn reach_through(&mut self, slot_idx: usize) {
+        let (start, end) = (self.slots[slot_idx].start, self.slots[slot_idx].end);
+        if self.reached[start] && !self.reached[end] {
+            self.reach(end);
+        } else if self.slots[slot_idx].two_way && self.reached[end] && !self.reached[start] {
+            self.reach(start);
+        }
+    }
+
+    // Picks a random unplaced slot side at a reached node, dropping stale entries on the way
+    fn pick_frontier<R: ChooseMultipleFill>(&mut self, rng: &mut R) -> Option<(usize, Side)> {
+        while !self.frontier.is_empty() {
+            let idx = random_index(rng, self.frontier.len());
+            let (slot_idx, side) = self.frontier[idx];
+            let slot = &self.slots[slot_idx];
+            let valid = !self.placed[slot_idx]
+                && match side {
+                    Side::Start => true,
+                    Side::End => self.reached[slot.end],
+                };
+            if valid {
+                return Some((slot_idx, side));
+            }
+            self.frontier.swap_remove(idx);
+            self.in_frontier[slot_idx][side.index()] = false;
+        }
+        None
+    }
+
+    // Picks the slot to connect with, going through the pool in a random order. Candidates that
+    // Kirby can leave come first. While parts of the graph are still out of reach, candidates
+    // that also open up new unplaced doors are preferred, otherwise the fill can wall itself off
+    // from the rest of the graph. Rooms are often entered through one pool and left through
+    // another, so doors of any pool count. Once nothing opens anything anymore, the remaining
+    // edges are connected uniformly.
+    fn pick_candidate<R: ChooseMultipleFill>(
+        &mut self,
+        rng: &mut R,
+        slot_idx: usize,
+        side: Side,
+    ) -> Option<usize> {
+        let pool = self.slots[slot_idx].pool;
+        let len = self.pools[pool].items.len();
+        let prefer_opening = len > 1 && self.unreached_doors > 0;
+        let mut first_leavable = None;
+        for position in 0..len {
+            let other = position + random_index(rng, len - position);
+            self.pools[pool].swap(position, other);
+            let candidate_idx = self.pools[pool].items[position];
+            let (from_idx, to_idx) = match side {
+                Side::Start => (slot_idx, candidate_idx),
+                Side::End => (candidate_idx, slot_idx),
+            };
+            let new_node = match side {
+                Side::Start => self.slots[candidate_idx].end,
+                Side::End => self.slots[candidate_idx].start,
+            };
+            let opening = !prefer_opening || self.opens_doors(new_node);
+            if (opening || first_leavable.is_none()) && self.can_leave(from_idx, to_idx) {
+                if opening {
+                    return Some(candidate_idx);
+                }
+                first_leavable = Some(candidate_idx);
+            }
+        }
+        first_leavable
+    }
+
+    fn remove_slot(&mut self, slot_idx: usize) {
+        self.placed[slot_idx] = true;
+        self.unplaced.remove(slot_idx);
+        self.pools[self.slots[slot_idx].pool].remove(slot_idx);
+        self.placed_by_pool[self.slots[slot_idx].pool].push(slot_idx);
+    }
+}
+
+// Forward fill: starting from the root, repeatedly take a random reachable edge that has not been
+// placed yet and connect it to a random unplaced edge from its pool. One-way edges can only be
+// reached from their start, while two-way edges can also be reached from their end. Every
+// swappable edge is placed exactly once, and an edge only keeps its vanilla connection if it picks
+// it like any other.
+fn fill_edges<N, E, G, R>(graph: &mut G, fill_graph: &FillGraph<N, E>, rng: &mut R) -> Result<()>
 where
-    R: RandomBool + ChooseMultipleFill,
+    E: Copy + Eq + Hash,
+    G: Graph<N, E>,
+    R: ChooseMultipleFill,
 {
+    let mut fill = Fill::new(fill_graph);
+    while !fill.unplaced.items.is_empty() {
+        // If nothing unplaced is reachable, the rest of the graph is disconnected from the root.
+        // Place it anyway and let the beatability check decide.
+        let (slot_idx, side) = match fill.pick_frontier(rng) {
+            Some(picked) => picked,
+            None => {
+                let idx = random_index(rng, fill.unplaced.items.len());
+                (fill.unplaced.items[idx], Side::Start)
+            }
+        };
+        let candidate = fill.pick_candidate(rng, slot_idx, side);
+        let candidate_idx = candidate.unwrap_or(fill.pools[fill.slots[slot_idx].pool].items[0]);
+
+        // `from` keeps its start and takes over the end of `to`
+        let (from_idx, to_idx) = match side {
+            Side::Start => (slot_idx, candidate_idx),
+            Side::End => (candidate_idx, slot_idx),
+        };
+        let end = fill.slots[to_idx].end;
+        fill.connect(from_idx, to_idx);
+        if from_idx != to_idx {
+            let (new_from, new_to) =
+                graph.swap_edges(fill.slots[from_idx].edge, fill.slots[to_idx].edge)?;
+            let old_end = fill.slots[from_idx].end;
+            fill.slots[from_idx].edge = new_from;
+            fill.slots[from_idx].end = end;
+            let to = &mut fill.slots[to_idx];
+            to.edge = new_to;
+            to.end = old_end;
+            if to.two_way {
+                fill.slot_ends[old_end].push(to_idx);
+                if fill.reached[old_end] {
+                    fill.add_to_frontier(to_idx, Side::End);
+                }
+            }
+        }
+        fill.remove_slot(from_idx);
+        fill.reach_through(from_idx);
+
+        // If no candidate could be left, the layout is rejected once the fill is done unless
+        // the edge can trade with one placed before it
+        if candidate.is_none() {
+            if let Some(other_idx) = fill.find_trade(rng, from_idx) {
+                let (new_from, new_other) =
+                    graph.swap_edges(fill.slots[from_idx].edge, fill.slots[other_idx].edge)?;
+                fill.slots[from_idx].edge = new_from;
+                fill.slots[other_idx].edge = new_other;
+                fill.reach_through(from_idx);
+                fill.reach_through(other_idx);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// A random index into a list of the given length, without building a list of indices
+fn random_index<R: ChooseMultipleFill>(rng: &mut R, len: usize) -> usize {
+    let mut buf = [0];
+    rng.choose_multiple_fill(0..len, &mut buf);
+    buf[0]
+}
+
+fn choose_random<T: Copy, R: ChooseMultipleFill>(rng: &mut R, items: &[T]) -> Option<T> {
+    let mut buf = [*items.first()?];
+    rng.choose_multiple_fill(items.iter().copied(), &mut buf);
+    Some(buf[0])
+}
+
+// Fisher-Yates shuffle
+fn shuffle<T, R: ChooseMultipleFill>(rng: &mut R, items: &mut [T]) {
+    for idx in (1..items.len()).rev() {
+        items.swap(idx, random_index(rng, idx + 1));
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use config::Config;
-
-    const MOCK_CONFIG: Config = config::Config {
-        seed: 0,
-        entrance_shuffle: EntranceShuffleType::Standard,
+    use crate::{
+        ability::{Ability, AbilitySource, AbilityTier},
+        boss::BossRoom,
+        enemy::EnemySlot,
+        game_graph::{GameGraph, GraphData},
+        graph::{Connection, EdgePool, Placement, Reachability},
+        item::Chest,
+        katam_rng::KatamRng,
+        music::MusicSlot,
+        palette::{KirbyPalette, SprayPaint},
     };
+    use config::{Config, Plando};
+
+    type Address = usize;
+    type Destination = [u8; 4];
 
     struct MockRng;
 
-    impl Rng for MockRng {
-        fn get_bool(&mut self, _p: f64) -> bool {
-            true
+    impl ChooseMultipleFill for MockRng {
+        fn choose_multiple_fill<T, I: Iterator<Item = T>>(
+            &mut self,
+            _iter: I,
+            _buf: &mut [T],
+        ) -> usize {
+            0
         }
     }
 
-    struct MockRomWriter;
+    struct MockRom;
 
-    impl RomWriter for MockRomWriter {
-        fn write_data<N, E>(&mut self, _data: impl Graph<N, E>) -> Result<()> {
+    impl Rom for MockRom {
+        fn write_data<N, E, G>(&mut self, _graph: &mut G) -> std::result::Result<(), WriteDataError>
+        where
+            N: Debug + Eq + Hash,
+            G: Graph<N, E>
+                + DoorData<N>
+                + ChestData<N>
+                + AbilitySourceData<N>
+                + EnemyData<N>
+                + BossData<N>
+                + MusicData
+                + PaletteData,
+        {
             Ok(())
         }
     }
 
-    struct MockGraph;
+    #[derive(Default, Clone)]
+    struct MockGraph {
+        door_data: HashMap<u32, (Destination, Vec<Address>)>,
+        ability_ids: HashMap<Ability, u8>,
+        start: u32,
+        goals: Vec<u32>,
+        decoupled: bool,
+        area_restricted: bool,
+        area_connections_only: bool,
+    }
 
     impl Graph<u32, u32> for MockGraph {
-        fn edge_count(&self) -> usize {
+        fn version(&self) -> u32 {
             0
         }
-        fn edge_endpoints(&self, _e: u32) -> Option<(u32, u32)> {
-            None
+        fn start(&self) -> &u32 {
+            &self.start
+        }
+        fn goals(&self) -> &[u32] {
+            &self.goals
+        }
+        fn swap_edges(
+            &mut self,
+            edge1: u32,
+            edge2: u32,
+        ) -> std::result::Result<(u32, u32), EdgeSwapError> {
+            Ok((edge1, edge2))
         }
-        fn edge_indices(&self) -> Vec<u32> {
+        fn decouple_edges(&mut self) {
+            self.decoupled = true;
+        }
+        fn exclude_edge(&mut self, _door: &u32) -> std::result::Result<(), ExcludeEdgeError> {
+            Ok(())
+        }
+        fn pin_edge(
+            &mut self,
+            _door: &u32,
+            _entrance: &u32,
+        ) -> std::result::Result<(), PinEdgeError> {
+            Ok(())
+        }
+        fn restrict_edges_to_areas(&mut self) {
+            self.area_restricted = true;
+        }
+        fn restrict_edges_to_area_connections(&mut self) {
+            self.area_connections_only = true;
+        }
+        fn get_edge_pools(&self) -> Vec<EdgePool<u32>> {
             vec![]
         }
-        fn add_edge(&mut self, _node1: u32, _node2: u32) -> u32 {
-            0
+        fn get_edge_endpoints(
+            &self,
+            edge: u32,
+        ) -> std::result::Result<(u32, u32), GetEdgeEndpointsError> {
+            Err(GetEdgeEndpointsError::NoEndpoints(edge as usize))
+        }
+        fn get_fixed_edges(&self) -> Vec<(u32, u32)> {
+            vec![]
+        }
+        fn extend_reachable_nodes(
+            &self,
+            _reachable: &mut HashSet<u32>,
+            _start: &u32,
+            _ignored_edges: &HashSet<u32>,
+        ) -> Vec<u32> {
+            vec![]
+        }
+        fn get_reachability(&self, _start: &u32) -> Reachability<u32> {
+            Reachability {
+                nodes: HashSet::new(),
+                abilities: HashSet::new(),
+                blocked_edges: vec![],
+                spheres: vec![],
+                previous: HashMap::new(),
+            }
+        }
+        fn warps(&self) -> &[u32] {
+            &[]
+        }
+        fn get_nodes_reaching(
+            &self,
+            _targets: &[u32],
+            _abilities: &HashSet<Ability>,
+        ) -> HashSet<u32> {
+            HashSet::new()
+        }
+        fn get_connections(&self) -> Vec<Connection<u32>> {
+            vec![]
+        }
+    }
+
+    impl ChestData<u32> for MockGraph {
+        fn chests(&self) -> &[Chest<u32>] {
+            &[]
+        }
+        fn set_chest_item(&mut self, _chest: usize, _item: Item) {}
+    }
+
+    impl AbilitySourceData<u32> for MockGraph {
+        fn ability_sources(&self) -> &[AbilitySource<u32>] {
+            &[]
+        }
+        fn set_source_ability(&mut self, _source: usize, _ability: Ability) {}
+        fn ability_ids(&self) -> &HashMap<Ability, u8> {
+            &self.ability_ids
+        }
+    }
+
+    impl MusicData for MockGraph {
+        fn music_slots(&self) -> &[MusicSlot] {
+            &[]
+        }
+        fn set_slot_track(&mut self, _slot: usize, _track: Track) {}
+    }
+
+    impl PaletteData for MockGraph {
+        fn kirby_palettes(&self) -> &[KirbyPalette] {
+            &[]
         }
-        fn remove_edge(&mut self, _e: u32) -> Option<()> {
+        fn set_kirby_color(&mut self, _kirby: Kirby, _color: Rgb) {}
+    }
+
+    impl BossData<u32> for MockGraph {
+        fn boss_rooms(&self) -> &[BossRoom<u32>] {
+            &[]
+        }
+        fn separate_boss_rooms(&mut self) {}
+        fn set_room_boss(
+            &mut self,
+            _room: usize,
+            _boss: Boss<u32>,
+        ) -> std::result::Result<(), SetBossError> {
+            Ok(())
+        }
+    }
+
+    impl EnemyData<u32> for MockGraph {
+        fn enemy_slots(&self) -> &[EnemySlot<u32>] {
+            &[]
+        }
+        fn set_slot_enemy(&mut self, _slot: usize, _enemy: Enemy) {}
+    }
+
+    impl DoorData<u32> for MockGraph {
+        fn door_data(&self) -> &HashMap<u32, (Destination, Vec<Address>)> {
+            &self.door_data
+        }
+        fn vanilla_door(&self, _entrance: &u32) -> Option<&u32> {
             None
         }
     }
 
     #[test]
     fn test_randomize_game() -> Result<()> {
+        let spoiler = randomize_katam(
+            Config::default(),
+            MockRng,
+            MockRng,
+            MockRng,
+            MockRom,
+            &mut MockGraph::default(),
+        )?;
+        assert_eq!(spoiler.shuffle_stats.attempts, 1);
+        assert!(spoiler.shuffle_stats.rejections.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_options_without_game_data() {
+        let randomize = |config| {
+            randomize_katam(
+                config,
+                MockRng,
+                MockRng,
+                MockRng,
+                MockRom,
+                &mut MockGraph::default(),
+            )
+        };
+        assert!(matches!(
+            randomize(Config {
+                shuffle_chests: true,
+                ..Config::default()
+            }),
+            Err(KatamRandoError::MissingGameData("chests", _))
+        ));
+        assert!(matches!(
+            randomize(Config {
+                ability_shuffle: AbilityShuffleType::Full,
+                ..Config::default()
+            }),
+            Err(KatamRandoError::MissingGameData(
+                "ability source addresses",
+                _
+            ))
+        ));
+        assert!(matches!(
+            randomize(Config {
+                enemy_shuffle: EnemyShuffleType::Full,
+                ..Config::default()
+            }),
+            Err(KatamRandoError::MissingGameData("enemy slots", _))
+        ));
+        assert!(matches!(
+            randomize(Config {
+                music_shuffle: MusicShuffleType::Full,
+                ..Config::default()
+            }),
+            Err(KatamRandoError::MissingGameData("music slots", _))
+        ));
+        assert!(matches!(
+            randomize(Config {
+                kirby_colors: [
+                    KirbyColor::Vanilla,
+                    KirbyColor::Random,
+                    KirbyColor::Vanilla,
+                    KirbyColor::Vanilla,
+                ],
+                ..Config::default()
+            }),
+            Err(KatamRandoError::MissingGameData("Kirby palettes", _))
+        ));
+    }
+
+    #[test]
+    fn test_chaos_shuffle_decouples_edges() -> Result<()> {
+        let config = Config {
+            entrance_shuffle: EntranceShuffleType::Chaos,
+            ..Config::default()
+        };
+        let mut graph = MockGraph::default();
+        randomize_katam(config, MockRng, MockRng, MockRng, MockRom, &mut graph)?;
+        assert!(graph.decoupled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_standard_shuffle_keeps_two_way_edges() -> Result<()> {
+        let mut graph = MockGraph::default();
         randomize_katam(
-            MOCK_CONFIG,
+            Config::default(),
+            MockRng,
+            MockRng,
+            MockRng,
+            MockRom,
+            &mut graph,
+        )?;
+        assert!(!graph.decoupled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_area_restricted_shuffle_restricts_edges() -> Result<()> {
+        let config = Config {
+            entrance_shuffle: EntranceShuffleType::AreaRestricted,
+            ..Config::default()
+        };
+        let mut graph = MockGraph::default();
+        randomize_katam(config, MockRng, MockRng, MockRng, MockRom, &mut graph)?;
+        assert!(graph.area_restricted);
+        assert!(!graph.decoupled);
+        Ok(())
+    }
+
+    fn load_game_graph() -> GameGraph {
+        let file_contents =
+            std::fs::read_to_string("doors.ron").expect("Error opening KatAM game data file.");
+        let graph_data: GraphData<String> =
+            ron::from_str(&file_contents).expect("Error deserializing KatAM game data");
+        GameGraph::new(graph_data)
+    }
+
+    fn destinations(graph: &GameGraph) -> HashMap<String, String> {
+        graph
+            .get_connections()
+            .into_iter()
+            .filter(|connection| connection.placement == Placement::Shuffled)
+            .map(|connection| (connection.door, connection.entrance))
+            .collect()
+    }
+
+    #[test]
+    fn test_vanilla_graph_beatable() {
+        let report = check_beatability(&load_game_graph());
+        assert!(report.is_beatable(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_beatability_report() {
+        let graph_data: GraphData<String> = ron::from_str(
+            r#"GraphData(
+                version: 1,
+                door_data: {},
+                ability_sources: [(node: "b", ability: Bomb)],
+                start: "a",
+                goals: ["b", "c", "d", "e"],
+                static_edges: [
+                    (start: "a", end: "b", two_way: true),
+                    (start: "a", end: "c", two_way: false, requires: Some(Ability(Cutter))),
+                    (start: "b", end: "d", two_way: false, requires: Some(Ability(Bomb))),
+                    (start: "e", end: "a", two_way: false),
+                ],
+                dynamic_edges: [],
+            )"#,
+        )
+        .expect("Error deserializing test graph data");
+        let report = check_beatability(&GameGraph::new(graph_data));
+
+        assert!(!report.is_beatable());
+        assert_eq!(
+            report.failed_goals,
+            vec![
+                (
+                    "c".to_string(),
+                    GoalFailure::MissingAbilities(vec![(
+                        "a".to_string(),
+                        "c".to_string(),
+                        Requirement::Ability(Ability::Cutter)
+                    )])
+                ),
+                ("e".to_string(), GoalFailure::NoPath),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_soft_locks() {
+        let graph_data: GraphData<String> = ron::from_str(
+            r#"GraphData(
+                version: 1,
+                door_data: {},
+                start: "a",
+                goals: ["b"],
+                warps: ["e"],
+                static_edges: [
+                    (start: "a", end: "b", two_way: true),
+                    (start: "b", end: "c", two_way: false),
+                    (start: "c", end: "d", two_way: true),
+                    (start: "a", end: "e", two_way: false),
+                    (start: "a", end: "f", two_way: false),
+                ],
+                dynamic_edges: [],
+            )"#,
+        )
+        .expect("Error deserializing test graph data");
+        let graph = GameGraph::new(graph_data);
+
+        // The game warps Kirby out of "e". "c" and "d" only lead to each other, and "f" is a dead
+        // end that isn't a warp.
+        let mut soft_locks = find_soft_locks(&graph);
+        soft_locks.sort();
+        assert_eq!(
+            soft_locks,
+            vec!["c".to_string(), "d".to_string(), "f".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_shuffle_chests_keeps_unreachable_chests_vanilla() {
+        let graph_data: GraphData<String> = ron::from_str(
+            r#"GraphData(
+                version: 1,
+                door_data: {},
+                chests: [
+                    (name: "Chest 1", node: "a", item: (name: "A", value: [1]), addresses: []),
+                    (name: "Chest 2", node: "b", item: (name: "B", value: [2]), addresses: []),
+                    (
+                        name: "Chest 3",
+                        node: "b",
+                        requires: Some(Ability(Bomb)),
+                        item: (name: "C", value: [3]),
+                        addresses: [],
+                    ),
+                    (name: "Chest 4", node: "c", item: (name: "D", value: [4]), addresses: []),
+                ],
+                start: "a",
+                goals: ["b"],
+                static_edges: [
+                    (start: "a", end: "b", two_way: true),
+                    (start: "a", end: "c", two_way: false, requires: Some(Ability(Cutter))),
+                ],
+                dynamic_edges: [],
+            )"#,
+        )
+        .expect("Error deserializing test graph data");
+        let vanilla = GameGraph::new(graph_data);
+
+        let mut swapped = false;
+        for seed in 0..20 {
+            let mut graph = vanilla.clone();
+            shuffle_chests(&mut graph, &mut KatamRng::new(seed));
+            let items: Vec<&str> = graph
+                .chests()
+                .iter()
+                .map(|chest| chest.item.name.as_str())
+                .collect();
+            assert!(items == vec!["A", "B", "C", "D"] || items == vec!["B", "A", "C", "D"]);
+            swapped |= items[0] == "B";
+        }
+        assert!(swapped);
+    }
+
+    // The goal needs Cutter, so one of the two sources has to keep giving it
+    fn ability_shuffle_graph() -> GameGraph {
+        let graph_data: GraphData<String> = ron::from_str(
+            r#"GraphData(
+                version: 1,
+                door_data: {},
+                ability_sources: [(node: "a", ability: Cutter), (node: "b", ability: Ufo)],
+                start: "a",
+                goals: ["c"],
+                static_edges: [
+                    (start: "a", end: "b", two_way: true),
+                    (start: "b", end: "c", two_way: false, requires: Some(Ability(Cutter))),
+                ],
+                dynamic_edges: [],
+            )"#,
+        )
+        .expect("Error deserializing test graph data");
+        GameGraph::new(graph_data)
+    }
+
+    #[test]
+    fn test_shuffle_abilities_same_tier() -> Result<()> {
+        let vanilla = ability_shuffle_graph();
+        for seed in 0..20 {
+            let mut graph = vanilla.clone();
+            shuffle_abilities(&mut graph, &mut KatamRng::new(seed), true)?;
+            let sources = graph.ability_sources();
+            // Cutter is the only common ability, so only it can get Kirby to the goal
+            assert_eq!(sources[0].ability, Ability::Cutter);
+            assert_eq!(sources[1].ability.tier(), AbilityTier::Rare);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_shuffle_abilities_full() -> Result<()> {
+        let vanilla = ability_shuffle_graph();
+        let mut moved = false;
+        for seed in 0..20 {
+            let mut graph = vanilla.clone();
+            shuffle_abilities(&mut graph, &mut KatamRng::new(seed), false)?;
+            assert!(is_beatable(&graph));
+            moved |= graph.ability_sources()[1].ability == Ability::Cutter;
+        }
+        assert!(moved);
+        Ok(())
+    }
+
+    fn enemy_shuffle_graph() -> GameGraph {
+        let graph_data: GraphData<String> = ron::from_str(
+            r#"GraphData(
+                version: 1,
+                door_data: {},
+                ability_sources: [(node: "b", ability: Sword)],
+                enemy_slots: [
+                    (
+                        name: "Enemy 1",
+                        node: "a",
+                        enemy: (name: "Waddle Dee", value: [1], class: Ground),
+                        addresses: [],
+                    ),
+                    (
+                        name: "Enemy 2",
+                        node: "a",
+                        enemy: (name: "Bronto Burt", value: [2], class: Flying),
+                        addresses: [],
+                    ),
+                    (
+                        name: "Enemy 3",
+                        node: "a",
+                        enemy: (name: "Glunk", value: [3], class: Water),
+                        addresses: [],
+                    ),
+                    (
+                        name: "Enemy 4",
+                        node: "b",
+                        enemy: (name: "Sword Knight", value: [4], class: Ground),
+                        addresses: [],
+                    ),
+                    (
+                        name: "Enemy 5",
+                        node: "b",
+                        enemy: (name: "Scarfy", value: [5], class: Flying),
+                        addresses: [],
+                    ),
+                ],
+                start: "a",
+                goals: ["b"],
+                static_edges: [(start: "a", end: "b", two_way: true)],
+                dynamic_edges: [],
+            )"#,
+        )
+        .expect("Error deserializing test graph data");
+        GameGraph::new(graph_data)
+    }
+
+    fn slot_enemies(graph: &GameGraph) -> Vec<&str> {
+        graph
+            .enemy_slots()
+            .iter()
+            .map(|slot| slot.enemy.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_shuffle_enemies_keeps_classes() {
+        let vanilla = enemy_shuffle_graph();
+        let mut replaced = false;
+        for seed in 0..20 {
+            let mut graph = vanilla.clone();
+            shuffle_enemies(&mut graph, &mut KatamRng::new(seed), false);
+            for (slot, vanilla_slot) in graph.enemy_slots().iter().zip(vanilla.enemy_slots()) {
+                assert_eq!(slot.enemy.class, vanilla_slot.enemy.class);
+            }
+            let enemies = slot_enemies(&graph);
+            assert_eq!(enemies[2], "Glunk");
+            replaced |= enemies[3] != "Sword Knight";
+        }
+        assert!(replaced);
+    }
+
+    #[test]
+    fn test_shuffle_enemies_keeps_ability_sources() {
+        let vanilla = enemy_shuffle_graph();
+        let mut replaced = false;
+        for seed in 0..20 {
+            let mut graph = vanilla.clone();
+            shuffle_enemies(&mut graph, &mut KatamRng::new(seed), true);
+            let enemies = slot_enemies(&graph);
+            assert_eq!(enemies[3..], ["Sword Knight", "Scarfy"]);
+            replaced |= enemies[0] != "Waddle Dee";
+        }
+        assert!(replaced);
+    }
+
+    #[test]
+    fn test_shuffle_stats_display() {
+        let mut stats = ShuffleStats {
+            attempts: 4,
+            ..ShuffleStats::default()
+        };
+        stats.reject(RejectionReason::SoftLock);
+        stats.reject(RejectionReason::SoftLock);
+        stats.reject(RejectionReason::UnreachableGoal);
+        assert_eq!(
+            stats.to_string(),
+            "4 shuffle attempt(s), 2 rejected for soft-lock, 1 rejected for unreachable goal"
+        );
+    }
+
+    #[test]
+    fn test_standard_shuffle_beatable() -> Result<()> {
+        let mut graph = load_game_graph();
+        let vanilla = destinations(&graph);
+        let known_soft_locks: HashSet<String> = find_soft_locks(&graph).into_iter().collect();
+        standard_shuffle(&mut graph, &mut KatamRng::new(0), &FixedEdges::default())?;
+        assert!(is_beatable(&graph));
+        assert!(find_soft_locks(&graph)
+            .iter()
+            .all(|node| known_soft_locks.contains(node)));
+
+        let shuffled = destinations(&graph);
+        assert_eq!(vanilla.len(), shuffled.len());
+        assert!(vanilla.keys().all(|start| shuffled.contains_key(start)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_playthrough_reaches_every_goal() -> Result<()> {
+        let mut graph = load_game_graph();
+        standard_shuffle(&mut graph, &mut KatamRng::new(0), &FixedEdges::default())?;
+        let playthrough = Playthrough::new(&graph);
+        assert_eq!(playthrough.goal_paths.len(), graph.goals().len());
+        for goal_path in &playthrough.goal_paths {
+            let path = goal_path.path.as_ref().expect("Goal should be reachable");
+            assert_eq!(path.first(), Some(graph.start()));
+            assert_eq!(path.last(), Some(&goal_path.goal));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_shuffle_bosses_keeps_kinds() -> Result<()> {
+        let vanilla = load_game_graph();
+        let mut graph = vanilla.clone();
+        shuffle_bosses(&mut graph, &mut KatamRng::new(0))?;
+        assert!(is_beatable(&graph));
+        let mut vanilla_bosses: Vec<&str> = vec![];
+        let mut bosses: Vec<&str> = vec![];
+        for (room, vanilla_room) in graph.boss_rooms().iter().zip(vanilla.boss_rooms()) {
+            assert_eq!(room.boss.kind, vanilla_room.boss.kind);
+            vanilla_bosses.push(&vanilla_room.boss.name);
+            bosses.push(&room.boss.name);
+        }
+        assert_ne!(bosses, vanilla_bosses);
+        bosses.sort_unstable();
+        vanilla_bosses.sort_unstable();
+        assert_eq!(bosses, vanilla_bosses);
+
+        // Boss doors lead to the new bosses, and the door shuffle leaves them alone
+        standard_shuffle(&mut graph, &mut KatamRng::new(0), &FixedEdges::default())?;
+        for room in graph.boss_rooms() {
+            for door in &room.doors {
+                assert!(graph.get_connections().iter().any(|connection| {
+                    &connection.door == door
+                        && connection.entrance == room.boss.entrance
+                        && connection.placement == Placement::Boss
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    struct MockMusic(Vec<MusicSlot>);
+
+    impl MusicData for MockMusic {
+        fn music_slots(&self) -> &[MusicSlot] {
+            &self.0
+        }
+        fn set_slot_track(&mut self, slot: usize, track: Track) {
+            self.0[slot].track = track;
+        }
+    }
+
+    fn music_slot(name: &str, track: &str, jingle: bool) -> MusicSlot {
+        MusicSlot {
+            name: name.to_string(),
+            track: Track {
+                name: track.to_string(),
+                value: vec![],
+                jingle,
+            },
+            addresses: vec![],
+        }
+    }
+
+    #[test]
+    fn test_shuffle_music_exclude_jingles() {
+        let vanilla: Vec<MusicSlot> = (0..20)
+            .map(|i| music_slot(&format!("Room {}", i), &format!("Track {}", i % 4), false))
+            .chain(vec![music_slot("Boss defeated", "Fanfare", true)])
+            .collect();
+        let mut music = MockMusic(vanilla.clone());
+        shuffle_music(&mut music, &mut KatamRng::new(0), true);
+        assert_eq!(music.0.last(), vanilla.last());
+        assert!(
+            music
+                .0
+                .iter()
+                .filter(|slot| slot.track.name == "Fanfare")
+                .count()
+                == 1
+        );
+        assert_ne!(music.0, vanilla);
+
+        let mut music = MockMusic(vanilla.clone());
+        shuffle_music(&mut music, &mut KatamRng::new(0), false);
+        // Every track plays as often as it does in vanilla
+        let track_names = |slots: &[MusicSlot]| {
+            let mut names: Vec<String> = slots.iter().map(|slot| slot.track.name.clone()).collect();
+            names.sort_unstable();
+            names
+        };
+        assert_eq!(track_names(&music.0), track_names(&vanilla));
+    }
+
+    struct MockPalettes(Vec<KirbyPalette>);
+
+    impl PaletteData for MockPalettes {
+        fn kirby_palettes(&self) -> &[KirbyPalette] {
+            &self.0
+        }
+        fn set_kirby_color(&mut self, kirby: Kirby, color: Rgb) {
+            for palette in self.0.iter_mut().filter(|palette| palette.kirby == kirby) {
+                palette.color = Some(color);
+            }
+        }
+    }
+
+    #[test]
+    fn test_color_kirbys() {
+        let mut palettes = MockPalettes(
+            Kirby::ALL
+                .iter()
+                .map(|kirby| KirbyPalette {
+                    kirby: *kirby,
+                    addresses: vec![],
+                    color: None,
+                })
+                .collect(),
+        );
+        let custom = Rgb::new(0x12, 0x34, 0x56);
+        color_kirbys(
+            &mut palettes,
+            &mut KatamRng::new(0),
+            &[
+                KirbyColor::Vanilla,
+                KirbyColor::Random,
+                KirbyColor::Preset(SprayPaint::Carbon),
+                KirbyColor::Custom(custom),
+            ],
+        );
+        let colors: Vec<Option<Rgb>> = palettes.0.iter().map(|palette| palette.color).collect();
+        assert_eq!(colors[0], None);
+        assert!(colors[1].is_some());
+        assert_eq!(colors[2], Some(SprayPaint::Carbon.color()));
+        assert_eq!(colors[3], Some(custom));
+    }
+
+    #[test]
+    fn test_chaos_shuffle_beatable() -> Result<()> {
+        let mut graph = load_game_graph();
+        chaos_shuffle(&mut graph, &mut KatamRng::new(0), &FixedEdges::default())?;
+        assert!(is_beatable(&graph));
+        Ok(())
+    }
+
+    #[test]
+    fn test_area_restricted_shuffle_beatable() -> Result<()> {
+        fn area(node: &str) -> &str {
+            node.split(':').next().unwrap_or(node)
+        }
+
+        let vanilla = destinations(&load_game_graph());
+        for seed in 0..5 {
+            let mut graph = load_game_graph();
+            area_restricted_shuffle(&mut graph, &mut KatamRng::new(seed), &FixedEdges::default())?;
+            assert!(is_beatable(&graph));
+
+            for (start, end) in destinations(&graph) {
+                assert_eq!(area(&end), area(&vanilla[&start]), "{} -> {}", start, end);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_area_connection_shuffle_keeps_areas_vanilla() -> Result<()> {
+        let config = Config {
+            entrance_shuffle: EntranceShuffleType::AreaConnections,
+            ..Config::default()
+        };
+        let mut mock_graph = MockGraph::default();
+        randomize_katam(config, MockRng, MockRng, MockRng, MockRom, &mut mock_graph)?;
+        assert!(mock_graph.area_connections_only);
+
+        let mut graph = load_game_graph();
+        let vanilla = destinations(&graph);
+        area_connection_shuffle(&mut graph, &mut KatamRng::new(0), &FixedEdges::default())?;
+        assert!(is_beatable(&graph));
+
+        let shuffled = destinations(&graph);
+        let changed: Vec<&String> = vanilla
+            .keys()
+            .filter(|start| vanilla[*start] != shuffled[*start])
+            .collect();
+        assert!(!changed.is_empty());
+        let graph_data: GraphData<String> = ron::from_str(
+            &std::fs::read_to_string("doors.ron").expect("Error opening KatAM game data file."),
+        )
+        .expect("Error deserializing KatAM game data");
+        // Tagged edges lead from one area into another
+        let area = |node: &str| node.split(':').next().map(String::from);
+        assert!(graph_data
+            .dynamic_edges
+            .iter()
+            .filter(|edge| edge.area_connection)
+            .all(|edge| area(&edge.start) != area(&edge.end)));
+        let area_connections: HashSet<String> = graph_data
+            .dynamic_edges
+            .into_iter()
+            .filter(|edge| edge.area_connection)
+            .flat_map(|edge| vec![edge.start, edge.end])
+            .collect();
+        assert!(changed
+            .iter()
+            .all(|start| area_connections.contains(*start)));
+        Ok(())
+    }
+
+    fn plando(connections: &[(&str, &str)]) -> Plando {
+        Plando {
+            connections: connections
+                .iter()
+                .map(|(door, entrance)| config::PlandoConnection {
+                    door: door.to_string(),
+                    entrance: entrance.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_plando_connections_are_kept() -> Result<()> {
+        let door = "PP: Box Boxer door in miniboss central";
+        let entrance = "RRoute: Entrance to master hand goal room";
+        let config = Config {
+            plando: plando(&[(door, entrance)]),
+            ..Config::default()
+        };
+        let mut graph = load_game_graph();
+        let spoiler = randomize_katam(
+            config,
+            KatamRng::new(0),
+            MockRng,
+            MockRng,
+            MockRom,
+            &mut graph,
+        )?;
+        assert!(is_beatable(&graph));
+        assert_eq!(destinations(&graph).get(door), None);
+        assert!(spoiler.areas["PP"]
+            .iter()
+            .any(|connection| connection.door == door
+                && connection.entrance == entrance
+                && connection.placement == Placement::Pinned));
+        assert!(graph
+            .get_reachable_nodes(&door.to_string(), &HashSet::new())
+            .contains(entrance));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plando_unknown_door() {
+        let config = Config {
+            plando: plando(&[(
+                "PP: Not a door",
+                "RRoute: Entrance to master hand goal room",
+            )]),
+            ..Config::default()
+        };
+        let result = randomize_katam(
+            config,
+            KatamRng::new(0),
+            MockRng,
+            MockRng,
+            MockRom,
+            &mut load_game_graph(),
+        );
+        assert!(matches!(
+            result,
+            Err(KatamRandoError::Plando(PinEdgeError::UnknownDoor(door))) if door == "PP: Not a door"
+        ));
+    }
+
+    #[test]
+    fn test_excluded_doors_stay_vanilla() -> Result<()> {
+        let door = "RRoute: Door in rolly hill purple cave";
+        let config = Config {
+            excluded_doors: vec![door.to_string()],
+            ..Config::default()
+        };
+        let mut graph = load_game_graph();
+        let spoiler = randomize_katam(
+            config,
+            KatamRng::new(0),
             MockRng,
-            MockRomWriter,
-            MockAlgorithms,
-            MockGraph,
+            MockRng,
+            MockRom,
+            &mut graph,
+        )?;
+        assert_eq!(destinations(&graph).get(door), None);
+        assert!(spoiler.excluded_doors.contains(&door.to_string()));
+        assert!(graph
+            .get_reachable_nodes(&door.to_string(), &HashSet::new())
+            .contains("RRoute: Entrance to master hand goal room"));
+
+        // Boss rooms are excluded in the game data
+        assert!(destinations(&graph)
+            .values()
+            .all(|entrance| !graph.goals().contains(entrance)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_boss_doors_with_boss_shuffle() {
+        let door = "CCav: Door in Moley prep room";
+        let randomize = |config| {
+            randomize_katam(
+                config,
+                KatamRng::new(0),
+                MockRng,
+                MockRng,
+                MockRom,
+                &mut load_game_graph(),
+            )
+        };
+        assert!(matches!(
+            randomize(Config {
+                shuffle_bosses: true,
+                excluded_doors: vec![door.to_string()],
+                ..Config::default()
+            }),
+            Err(KatamRandoError::FixedBossDoor(node)) if node == door
+        ));
+        assert!(matches!(
+            randomize(Config {
+                shuffle_bosses: true,
+                plando: plando(&[(door, "RRoute: Entrance to master hand goal room")]),
+                ..Config::default()
+            }),
+            Err(KatamRandoError::FixedBossDoor(node)) if node == door
+        ));
+    }
+
+    #[test]
+    fn test_unbeatable_game_data() {
+        let graph_data: GraphData<String> = ron::from_str(
+            r#"GraphData(
+                version: 1,
+                door_data: {},
+                start: "a",
+                goals: ["d"],
+                static_edges: [
+                    (start: "a", end: "b", two_way: false),
+                ],
+                dynamic_edges: [
+                    (start: "b", end: "c", two_way: false),
+                ],
+            )"#,
+        )
+        .expect("Error deserializing test graph data");
+        let result = randomize_katam(
+            Config::default(),
+            MockRng,
+            MockRng,
+            MockRng,
+            MockRom,
+            &mut GameGraph::new(graph_data),
+        );
+        assert!(matches!(
+            result,
+            Err(KatamRandoError::UnbeatableGameData(_))
+        ));
+    }
+
+    #[test]
+    fn test_plando_soft_lock() {
+        let graph_data: GraphData<String> = ron::from_str(
+            r#"GraphData(
+                version: 1,
+                door_data: {},
+                start: "a",
+                goals: ["f"],
+                static_edges: [
+                    (start: "a", end: "c", two_way: false),
+                    (start: "d", end: "e", two_way: false),
+                    (start: "f", end: "a", two_way: false),
+                ],
+                dynamic_edges: [
+                    (start: "c", end: "d", two_way: false),
+                    (start: "e", end: "f", two_way: false),
+                ],
+            )"#,
         )
+        .expect("Error deserializing test graph data");
+        let config = Config {
+            plando: plando(&[("e", "d")]),
+            ..Config::default()
+        };
+
+        // The only way out of "d" and "e" now leads back into "d"
+        let result = randomize_katam(
+            config,
+            MockRng,
+            MockRng,
+            MockRng,
+            MockRom,
+            &mut GameGraph::new(graph_data),
+        );
+        match result {
+            Err(e @ KatamRandoError::PlandoSoftLock(_, _)) => assert_eq!(
+                e.to_string(),
+                r#"Invalid plando: connecting "e" to "d" leaves Kirby stuck behind "d""#
+            ),
+            _ => panic!("Expected a plando soft-lock error"),
+        }
+    }
+
+    // Measures how often each door keeps its vanilla destination across many seeds. A uniform
+    // placement keeps a door vanilla with probability 1 / (pool size), so anything far above
+    // that means the shuffle is biased towards the vanilla layout.
+    #[test]
+    fn test_standard_shuffle_vanilla_bias() -> Result<()> {
+        let seeds = 30;
+        let graph = load_game_graph();
+        let vanilla = destinations(&graph);
+        let mut vanilla_counts: HashMap<String, usize> = HashMap::new();
+
+        for seed in 0..seeds {
+            let mut shuffled_graph = graph.clone();
+            standard_shuffle(
+                &mut shuffled_graph,
+                &mut KatamRng::new(seed),
+                &FixedEdges::default(),
+            )?;
+            for (start, end) in destinations(&shuffled_graph) {
+                if vanilla[&start] == end {
+                    *vanilla_counts.entry(start).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let vanilla_rate =
+            vanilla_counts.values().sum::<usize>() as f64 / (vanilla.len() as u64 * seeds) as f64;
+        let max_door_rate =
+            vanilla_counts.values().copied().max().unwrap_or(0) as f64 / seeds as f64;
+        assert!(
+            vanilla_rate < 0.02,
+            "Doors stayed vanilla {:.2}% of the time",
+            vanilla_rate * 100.0
+        );
+        assert!(
+            max_door_rate < 0.25,
+            "A door stayed vanilla in {:.2}% of seeds",
+            max_door_rate * 100.0
+        );
+        Ok(())
     }
 }
@@ -1,4 +1,8 @@
-use crate::graph::{DoorData, Graph};
+use crate::ability::Ability;
+use crate::graph::{
+    AbilitySourceData, BossData, ChestData, DoorData, EnemyData, Graph, MusicData, PaletteData,
+};
+use crate::patch::{self, ApplyPatchError};
 use std::{cmp::Eq, fmt::Debug, hash::Hash};
 use thiserror::Error;
 
@@ -15,9 +19,42 @@ pub struct ByteWriteError {
 #[error("Errors writing bytes to addresses: {0:?}")]
 pub struct WriteAddressesError(pub Vec<ByteWriteError>);
 
+// Problems with the game data or the ROM that keep the randomized game from being written
+#[derive(Error, Debug)]
+pub enum WriteDataError {
+    #[error("No ROM addresses found for door {0}")]
+    NoAddresses(String),
+    #[error("No destination found for entrance {0}")]
+    NoDestination(String),
+    #[error("No ID found for ability {0:?}")]
+    NoAbilityId(Ability),
+    #[error("Failed to write {0}: {1}")]
+    WriteAddresses(String, WriteAddressesError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
 pub trait Rom {
-    fn write_data<N, E, G>(&mut self, graph: &mut G) -> Result<(), std::io::Error>
+    fn write_data<N, E, G>(&mut self, graph: &mut G) -> Result<(), WriteDataError>
     where
         N: Debug + Eq + Hash,
-        G: Graph<N, E> + DoorData<N>;
+        G: Graph<N, E>
+            + DoorData<N>
+            + ChestData<N>
+            + AbilitySourceData<N>
+            + EnemyData<N>
+            + BossData<N>
+            + MusicData
+            + PaletteData;
+}
+
+// Applies a BPS or IPS patch, telling them apart by their header
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, ApplyPatchError> {
+    if patch.starts_with(patch::BPS_MAGIC) {
+        patch::apply_bps(rom, patch)
+    } else if patch.starts_with(patch::IPS_MAGIC) {
+        patch::apply_ips(rom, patch)
+    } else {
+        Err(ApplyPatchError::UnknownFormat)
+    }
 }
@@ -1,16 +1,22 @@
 use crate::{
-    graph::{DoorData, Graph},
-    rom::{ByteWriteError, Rom, WriteAddressesError},
+    graph::{
+        AbilitySourceData, BossData, ChestData, Connection, DoorData, EnemyData, Graph, MusicData,
+        PaletteData,
+    },
+    patch::{self, OutputFormat},
+    rom::{ByteWriteError, Rom, WriteAddressesError, WriteDataError},
 };
 use std::{
     cmp::Eq,
+    convert::TryInto,
     fmt::Debug,
     fs::File,
     hash::Hash,
-    io::{Read, Write},
+    io::{self, Read, Write},
 };
 
 type Address = usize;
+type Destination = [u8; 4];
 
 pub trait RomRead {
     fn read_rom(&mut self, buf: &mut Vec<u8>) -> Result<(), std::io::Error>;
@@ -22,6 +28,24 @@ pub trait RomWrite {
 
 pub struct RomFile<'a, R: RomRead + RomWrite> {
     pub rom_file: &'a mut R,
+    // Whether the randomized ROM or a patch for the clean ROM is written
+    pub output: OutputFormat,
+}
+
+// A ROM held in memory. Writing replaces its contents.
+impl RomRead for Vec<u8> {
+    fn read_rom(&mut self, buf: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        buf.extend_from_slice(self);
+        Ok(())
+    }
+}
+
+impl RomWrite for Vec<u8> {
+    fn write_rom(&mut self, buf: &[u8]) -> Result<(), std::io::Error> {
+        self.clear();
+        self.extend_from_slice(buf);
+        Ok(())
+    }
 }
 
 impl RomRead for File {
@@ -38,45 +62,116 @@ impl RomWrite for File {
 }
 
 impl<'a, R: RomRead + RomWrite> Rom for RomFile<'a, R> {
-    fn write_data<N, E, G>(&mut self, graph: &mut G) -> Result<(), std::io::Error>
+    fn write_data<N, E, G>(&mut self, graph: &mut G) -> Result<(), WriteDataError>
     where
         N: Debug + Eq + Hash,
-        G: Graph<N, E> + DoorData<N>,
+        G: Graph<N, E>
+            + DoorData<N>
+            + ChestData<N>
+            + AbilitySourceData<N>
+            + EnemyData<N>
+            + BossData<N>
+            + MusicData
+            + PaletteData,
     {
+        let door_data = graph.door_data();
+
         let mut buffer = Vec::new();
         self.rom_file.read_rom(&mut buffer)?;
+        // Patches are made against the clean ROM
+        let source = match self.output {
+            OutputFormat::Rom => vec![],
+            OutputFormat::Bps | OutputFormat::Ips => buffer.clone(),
+        };
+
+        // Entrances without door data of their own keep the destination a door that leads to them
+        // has in the clean ROM, so everything is looked up before the first write
+        let destination = |entrance: &N| -> Option<Destination> {
+            match door_data.get(entrance) {
+                Some((dest, _)) => Some(*dest),
+                None => graph
+                    .vanilla_door(entrance)
+                    .and_then(|door| door_data.get(door))
+                    .and_then(|(_, addresses)| addresses.first())
+                    .and_then(|address| buffer.get(*address..*address + 4))
+                    .and_then(|bytes| bytes.try_into().ok()),
+            }
+        };
+        let door_writes = graph
+            .get_connections()
+            .into_iter()
+            .map(|Connection { door, entrance, .. }| {
+                let addresses = door_data
+                    .get(&door)
+                    .map(|(_, addresses)| addresses)
+                    .ok_or_else(|| WriteDataError::NoAddresses(format!("{:?}", door)))?;
+                let dest = destination(&entrance)
+                    .ok_or_else(|| WriteDataError::NoDestination(format!("{:?}", entrance)))?;
+                Ok((door, dest, addresses))
+            })
+            .collect::<Result<Vec<(N, Destination, &Vec<Address>)>, WriteDataError>>()?;
 
-        for (start_node_id, end_node_id) in graph.get_edges() {
-            // TODO: Debug log level
-            println!("edge: {:?}, {:?}", start_node_id, end_node_id);
-
-            let addresses_to_replace = graph
-                .door_data()
-                .get(&start_node_id)
-                .map(|t| t.1.clone())
-                .unwrap_or_else(|| {
-                    panic!(
-                        "No ROM addresses found for start node ID {:?}",
-                        start_node_id
-                    )
-                });
-
-            let dest = graph
-                .door_data()
-                .get(&end_node_id)
-                .map(|t| t.0)
-                .unwrap_or_else(|| {
-                    panic!(
-                        "No destination data found for end node ID {:?}",
-                        end_node_id
-                    )
-                });
-
-            write_addresses(&mut buffer, &dest, &addresses_to_replace)
-                .unwrap_or_else(|e| panic!("Failed to write to rom addresses: {:?}", e));
+        for (door, dest, addresses) in door_writes {
+            write_addresses(&mut buffer, &dest, addresses)
+                .map_err(|e| WriteDataError::WriteAddresses(format!("door {:?}", door), e))?;
         }
 
-        self.rom_file.write_rom(&buffer)?;
+        for chest in graph.chests() {
+            write_addresses(&mut buffer, &chest.item.value, &chest.addresses).map_err(|e| {
+                WriteDataError::WriteAddresses(format!("the item of chest {}", chest.name), e)
+            })?;
+        }
+
+        for source in graph.ability_sources() {
+            // Sources without addresses always keep their vanilla ability, since ability shuffle
+            // can't be turned on without them
+            if source.addresses.is_empty() {
+                continue;
+            }
+            let id = graph
+                .ability_ids()
+                .get(&source.ability)
+                .ok_or(WriteDataError::NoAbilityId(source.ability))?;
+            write_addresses(&mut buffer, &[*id], &source.addresses).map_err(|e| {
+                WriteDataError::WriteAddresses(
+                    format!("the ability of the source at {:?}", source.node),
+                    e,
+                )
+            })?;
+        }
+
+        for slot in graph.enemy_slots() {
+            write_addresses(&mut buffer, &slot.enemy.value, &slot.addresses).map_err(|e| {
+                WriteDataError::WriteAddresses(format!("the enemy of slot {}", slot.name), e)
+            })?;
+        }
+
+        for slot in graph.music_slots() {
+            write_addresses(&mut buffer, &slot.track.value, &slot.addresses).map_err(|e| {
+                WriteDataError::WriteAddresses(format!("the track of music slot {}", slot.name), e)
+            })?;
+        }
+
+        for palette in graph.kirby_palettes() {
+            if let Some(color) = palette.color {
+                write_addresses(&mut buffer, &color.palette_bytes(), &palette.addresses).map_err(
+                    |e| {
+                        WriteDataError::WriteAddresses(
+                            format!("the palette of {}", palette.kirby),
+                            e,
+                        )
+                    },
+                )?;
+            }
+        }
+
+        let output = match self.output {
+            OutputFormat::Rom => buffer,
+            OutputFormat::Bps => patch::create_bps(&source, &buffer),
+            OutputFormat::Ips => patch::create_ips(&source, &buffer)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        };
+        self.rom_file.write_rom(&output)?;
         Ok(())
     }
 }
@@ -120,6 +215,7 @@ fn write_addresses(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::game_graph::{GameGraph, GraphData};
 
     #[test]
     fn test_write_addresses() -> Result<(), String> {
@@ -202,4 +298,50 @@ mod tests {
         assert_eq!([0x03, 0x03, 0x87, 0xAD], buffer);
         Ok(())
     }
+
+    #[test]
+    fn test_write_entrances_without_door_data() -> Result<(), WriteDataError> {
+        let graph_data: GraphData<String> = ron::from_str(
+            r#"GraphData(
+                version: 1,
+                door_data: {
+                    "a": ((0, 0, 0, 0), [0]),
+                    "c": ((0, 0, 0, 0), [4]),
+                },
+                start: "a",
+                goals: [],
+                static_edges: [],
+                dynamic_edges: [
+                    (start: "a", end: "b", two_way: false),
+                    (start: "c", end: "d", two_way: false),
+                ],
+            )"#,
+        )
+        .expect("Error deserializing test graph data");
+        let mut graph = GameGraph::new(graph_data);
+        graph
+            .pin_edge(&"a".to_string(), &"d".to_string())
+            .expect("Error pinning test edge");
+
+        // Each door gets the destination the other door has in the clean ROM
+        let mut rom = vec![1, 1, 1, 1, 2, 2, 2, 2];
+        RomFile {
+            rom_file: &mut rom,
+            output: OutputFormat::Rom,
+        }
+        .write_data(&mut graph)?;
+        assert_eq!(rom, [2, 2, 2, 2, 1, 1, 1, 1]);
+
+        // The vanilla door of "d" is past the end of this ROM, so "d" has no destination
+        let mut rom = vec![1, 1, 1, 1, 2, 2];
+        let result = RomFile {
+            rom_file: &mut rom,
+            output: OutputFormat::Rom,
+        }
+        .write_data(&mut graph);
+        assert!(
+            matches!(result, Err(WriteDataError::NoDestination(entrance)) if entrance == "\"d\"")
+        );
+        Ok(())
+    }
 }
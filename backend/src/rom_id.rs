@@ -0,0 +1,248 @@
+use sha1::{Digest, Sha1};
+use std::fmt::Write;
+use thiserror::Error;
+
+// Where the fields of the GBA cartridge header are
+const TITLE: std::ops::Range<usize> = 0xA0..0xAC;
+const GAME_CODE: std::ops::Range<usize> = 0xAC..0xB0;
+const FIXED_VALUE_ADDRESS: usize = 0xB2;
+const FIXED_VALUE: u8 = 0x96;
+// The header checksum covers the bytes from the title up to the checksum itself
+const CHECKSUM_ADDRESS: usize = 0xBD;
+const HEADER_SIZE: usize = 0xC0;
+
+// Every release of the game shares the first three letters of its game code, and the last one
+// tells the region
+const GAME_CODE_PREFIX: &[u8] = b"B8K";
+
+// Only names the region in errors, since the randomizer only has the addresses of the USA release
+fn region_name(region_code: u8) -> &'static str {
+    match region_code {
+        b'E' => "USA",
+        b'P' => "European",
+        b'J' => "Japanese",
+        _ => "unknown",
+    }
+}
+
+// A clean dump the randomizer knows the addresses of
+pub struct KnownRom {
+    pub name: &'static str,
+    // The title in the header, without its zero padding
+    pub title: &'static str,
+    pub game_code: &'static [u8; 4],
+    pub crc32: u32,
+}
+
+// TODO: Add the European and Japanese releases once the game data has their addresses. Until then
+// they are rejected as unsupported.
+pub const KNOWN_ROMS: &[KnownRom] = &[KnownRom {
+    name: "Kirby & The Amazing Mirror (USA)",
+    title: "AGB KIRBY AM",
+    game_code: b"B8KE",
+    crc32: 0x9F2A3048,
+}];
+
+#[derive(Error, Debug, PartialEq)]
+pub enum RomIdError {
+    #[error("The file is only {0} bytes long, which is too short for a GBA ROM")]
+    TooShort(usize),
+    #[error("The file doesn't have a valid GBA header, so it is not a GBA ROM or is damaged")]
+    InvalidHeader,
+    #[error("The ROM is \"{title}\" ({game_code}), not Kirby & The Amazing Mirror")]
+    WrongGame { title: String, game_code: String },
+    #[error(
+        "The ROM is the {region} release of Kirby & The Amazing Mirror ({game_code}), but only {} \
+         can be randomized",
+        supported_roms()
+    )]
+    UnsupportedRegion { region: String, game_code: String },
+    #[error(
+        "The ROM is {name}, but it doesn't match a clean dump (CRC32 {crc32:08X}, SHA-1 {sha1}). \
+         It may be hacked, patched or already randomized."
+    )]
+    Modified {
+        name: &'static str,
+        crc32: u32,
+        sha1: String,
+    },
+}
+
+fn supported_roms() -> String {
+    KNOWN_ROMS
+        .iter()
+        .map(|rom| rom.name)
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+// Header text is padded with zeros and isn't always valid ASCII
+fn header_text(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|byte| **byte != 0)
+        .map(|byte| {
+            if byte.is_ascii_graphic() || *byte == b' ' {
+                char::from(*byte)
+            } else {
+                '?'
+            }
+        })
+        .collect()
+}
+
+fn header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE.start..CHECKSUM_ADDRESS]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte))
+        .wrapping_sub(0x19)
+}
+
+fn sha1_hex(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+// Checks that the ROM is a clean dump the randomizer can patch, since writing to the addresses of
+// the game data in any other file gives a broken game
+pub fn identify(rom: &[u8]) -> Result<&'static KnownRom, RomIdError> {
+    identify_in(rom, KNOWN_ROMS)
+}
+
+fn identify_in<'a>(rom: &[u8], known_roms: &'a [KnownRom]) -> Result<&'a KnownRom, RomIdError> {
+    if rom.len() < HEADER_SIZE {
+        return Err(RomIdError::TooShort(rom.len()));
+    }
+    if rom[FIXED_VALUE_ADDRESS] != FIXED_VALUE || rom[CHECKSUM_ADDRESS] != header_checksum(rom) {
+        return Err(RomIdError::InvalidHeader);
+    }
+
+    let title = header_text(&rom[TITLE]);
+    let game_code = &rom[GAME_CODE];
+    let game_code_text = header_text(game_code);
+    let wrong_game = || RomIdError::WrongGame {
+        title: title.clone(),
+        game_code: game_code_text.clone(),
+    };
+    if !game_code.starts_with(GAME_CODE_PREFIX) {
+        return Err(wrong_game());
+    }
+    let mut releases = known_roms
+        .iter()
+        .filter(|known| &known.game_code[..] == game_code)
+        .peekable();
+    let name = match releases.peek() {
+        // Another game can share a game code, but not its title as well
+        Some(known) if known.title != title => return Err(wrong_game()),
+        Some(known) => known.name,
+        None => {
+            return Err(RomIdError::UnsupportedRegion {
+                region: region_name(game_code[3]).to_string(),
+                game_code: game_code_text,
+            })
+        }
+    };
+
+    let crc32 = crc32fast::hash(rom);
+    releases
+        .find(|known| known.crc32 == crc32)
+        .ok_or_else(|| RomIdError::Modified {
+            name,
+            crc32,
+            sha1: sha1_hex(rom),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_rom(title: &[u8], game_code: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 0x200];
+        rom[TITLE.start..TITLE.start + title.len()].copy_from_slice(title);
+        rom[GAME_CODE].copy_from_slice(game_code);
+        rom[FIXED_VALUE_ADDRESS] = FIXED_VALUE;
+        rom[CHECKSUM_ADDRESS] = header_checksum(&rom);
+        rom
+    }
+
+    fn known_rom(rom: &[u8]) -> KnownRom {
+        KnownRom {
+            name: "Test ROM",
+            title: "AGB KIRBY AM",
+            game_code: b"B8KE",
+            crc32: crc32fast::hash(rom),
+        }
+    }
+
+    #[test]
+    fn test_identify_clean_rom() {
+        let rom = test_rom(b"AGB KIRBY AM", b"B8KE");
+        let known = [known_rom(&rom)];
+        assert_eq!(
+            identify_in(&rom, &known).map(|rom| rom.name),
+            Ok("Test ROM")
+        );
+    }
+
+    #[test]
+    fn test_identify_modified_rom() {
+        let rom = test_rom(b"AGB KIRBY AM", b"B8KE");
+        let known = [known_rom(&rom)];
+        let mut randomized = rom;
+        randomized[0x100] = 1;
+        match identify_in(&randomized, &known) {
+            Err(RomIdError::Modified { name, crc32, sha1 }) => {
+                assert_eq!(name, "Test ROM");
+                assert_eq!(crc32, crc32fast::hash(&randomized));
+                assert_eq!(sha1.len(), 40);
+            }
+            result => panic!("Expected a modified ROM, got {:?}", result.map(|r| r.name)),
+        }
+    }
+
+    #[test]
+    fn test_identify_wrong_roms() {
+        let rom = test_rom(b"AGB KIRBY AM", b"B8KE");
+        let known = [known_rom(&rom)];
+        assert_eq!(
+            identify_in(&rom[..0x80], &known).err(),
+            Some(RomIdError::TooShort(0x80))
+        );
+
+        let mut damaged = rom;
+        damaged[TITLE.start] = b'X';
+        assert_eq!(
+            identify_in(&damaged, &known).err(),
+            Some(RomIdError::InvalidHeader)
+        );
+
+        assert_eq!(
+            identify_in(&test_rom(b"POKEMON EMER", b"BPEE"), &known).err(),
+            Some(RomIdError::WrongGame {
+                title: "POKEMON EMER".to_string(),
+                game_code: "BPEE".to_string(),
+            })
+        );
+        assert_eq!(
+            identify_in(&test_rom(b"NOT KIRBY", b"B8KE"), &known).err(),
+            Some(RomIdError::WrongGame {
+                title: "NOT KIRBY".to_string(),
+                game_code: "B8KE".to_string(),
+            })
+        );
+
+        let japanese = identify_in(&test_rom(b"AGB KIRBY AM", b"B8KJ"), &known).err();
+        assert_eq!(
+            japanese,
+            Some(RomIdError::UnsupportedRegion {
+                region: "Japanese".to_string(),
+                game_code: "B8KJ".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    config::{Config, MusicShuffleType},
+    palette::KirbyColor,
+};
+use serde::Serialize;
+use std::fmt;
+
+const HASH_WORDS: [&str; 32] = [
+    "Kirby",
+    "Meta Knight",
+    "Dark Mind",
+    "Master Hand",
+    "Crazy Hand",
+    "Waddle Dee",
+    "Maxim Tomato",
+    "Warp Star",
+    "Beam",
+    "Bomb",
+    "Burning",
+    "Crash",
+    "Cupid",
+    "Cutter",
+    "Fighter",
+    "Hammer",
+    "Ice",
+    "Laser",
+    "Magic",
+    "Mini",
+    "Missile",
+    "Parasol",
+    "Sleep",
+    "Smash",
+    "Spark",
+    "Stone",
+    "Sword",
+    "Throw",
+    "Tornado",
+    "UFO",
+    "Wheel",
+    "Cell Phone",
+];
+const HASH_LENGTH: usize = 5;
+
+// FNV-1a, which unlike the std hashers is guaranteed to give the same result on every machine
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+// A few words racers can read out to each other to check they are playing the same seed
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SeedHash(pub Vec<&'static str>);
+
+impl SeedHash {
+    // Placements are what ended up where, e.g. a door and its entrance or a chest and its item
+    pub fn new(settings: &Config, data_version: u32, placements: &[(String, String)]) -> Self {
+        let mut placements: Vec<String> = placements
+            .iter()
+            .map(|(location, contents)| format!("{}\n{}\n", location, contents))
+            .collect();
+        placements.sort();
+
+        // Cosmetic settings are left out, so racers who picked different music or colors get the
+        // same hash. The settings string holds the seed too, and unlike the config's fields its
+        // encoding only changes along with its version.
+        let logic_settings = Config {
+            music_shuffle: MusicShuffleType::Off,
+            kirby_colors: [KirbyColor::Vanilla; 4],
+            ..settings.clone()
+        };
+        let mut input = logic_settings.to_settings_string().into_bytes();
+        input.extend_from_slice(&data_version.to_le_bytes());
+        for placement in placements {
+            input.extend_from_slice(placement.as_bytes());
+        }
+
+        // Each word takes 5 bits of the hash
+        let hash = fnv1a(&input);
+        SeedHash(
+            (0..HASH_LENGTH)
+                .map(|idx| HASH_WORDS[(hash >> (idx * 5)) as usize % HASH_WORDS.len()])
+                .collect(),
+        )
+    }
+}
+
+impl SeedHash {
+    // The words without spaces, for putting the hash in a file name
+    pub fn to_file_name(&self) -> String {
+        self.0
+            .iter()
+            .map(|word| word.replace(' ', ""))
+            .collect::<Vec<String>>()
+            .join("-")
+    }
+}
+
+impl fmt::Display for SeedHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join(" / "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(seed: u64) -> Config {
+        Config {
+            seed,
+            ..Config::default()
+        }
+    }
+
+    fn connections(entrances: &[&str]) -> Vec<(String, String)> {
+        entrances
+            .iter()
+            .enumerate()
+            .map(|(idx, entrance)| (format!("Door {}", idx), entrance.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn test_seed_hash_is_stable() {
+        let hash = SeedHash::new(&settings(0), 1, &connections(&["a", "b"]));
+        // Changing this breaks comparing hashes with older versions of the randomizer
+        assert_eq!(
+            hash.0,
+            vec!["Wheel", "Maxim Tomato", "Sleep", "Parasol", "Kirby"]
+        );
+        assert_eq!(
+            hash,
+            SeedHash::new(&settings(0), 1, &connections(&["a", "b"]))
+        );
+    }
+
+    #[test]
+    fn test_seed_hash_file_name() {
+        let hash = SeedHash(vec!["Kirby", "Meta Knight", "Cell Phone"]);
+        assert_eq!(hash.to_file_name(), "Kirby-MetaKnight-CellPhone");
+    }
+
+    #[test]
+    fn test_seed_hash_changes_with_inputs() {
+        let hash = SeedHash::new(&settings(0), 1, &connections(&["a", "b"]));
+        assert_ne!(
+            hash,
+            SeedHash::new(&settings(1), 1, &connections(&["a", "b"]))
+        );
+        assert_ne!(
+            hash,
+            SeedHash::new(&settings(0), 2, &connections(&["a", "b"]))
+        );
+        assert_ne!(
+            hash,
+            SeedHash::new(&settings(0), 1, &connections(&["b", "a"]))
+        );
+
+        let music = Config {
+            music_shuffle: MusicShuffleType::Full,
+            ..settings(0)
+        };
+        assert_eq!(hash, SeedHash::new(&music, 1, &connections(&["a", "b"])));
+        let colors = Config {
+            kirby_colors: [KirbyColor::Random; 4],
+            ..settings(0)
+        };
+        assert_eq!(hash, SeedHash::new(&colors, 1, &connections(&["a", "b"])));
+    }
+}
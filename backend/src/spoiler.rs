@@ -0,0 +1,582 @@
+use crate::{
+    ability::{Ability, AbilitySource},
+    boss::BossRoom,
+    config::Config,
+    enemy::EnemySlot,
+    game_graph::area,
+    graph::{Connection, Placement},
+    item::Chest,
+    music::MusicSlot,
+    palette::KirbyPalette,
+    playthrough::Playthrough,
+    randomizer::ShuffleStats,
+    seed_hash::SeedHash,
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt, fmt::Display};
+
+#[derive(Serialize)]
+pub struct SpoilerConnection {
+    pub door: String,
+    pub entrance: String,
+    pub placement: Placement,
+}
+
+#[derive(Serialize)]
+pub struct SpoilerChest {
+    pub chest: String,
+    pub item: String,
+}
+
+#[derive(Serialize)]
+pub struct SpoilerBoss {
+    pub door: String,
+    pub boss: String,
+}
+
+#[derive(Serialize)]
+pub struct SpoilerTrack {
+    pub slot: String,
+    pub track: String,
+}
+
+#[derive(Serialize)]
+pub struct SpoilerKirbyColor {
+    pub kirby: String,
+    pub color: String,
+}
+
+#[derive(Serialize)]
+pub struct SpoilerEnemy {
+    pub slot: String,
+    pub enemy: String,
+}
+
+#[derive(Serialize)]
+pub struct SpoilerAbilitySource {
+    pub node: String,
+    pub ability: Ability,
+}
+
+// What the settings shuffled besides doors. Anything left vanilla is left out of the spoiler.
+pub struct ShuffledContents<'a, N> {
+    pub chests: &'a [Chest<N>],
+    pub ability_sources: &'a [AbilitySource<N>],
+    pub enemy_slots: &'a [EnemySlot<N>],
+    pub boss_rooms: &'a [BossRoom<N>],
+    pub music_slots: &'a [MusicSlot],
+    // Only recolored palettes are listed
+    pub kirby_palettes: &'a [KirbyPalette],
+}
+
+// Everything needed to reproduce and follow a seed
+#[derive(Serialize)]
+pub struct Spoiler {
+    pub settings: Config,
+    // The settings as a string that can be shared to generate the same seed
+    pub settings_string: String,
+    pub hash: SeedHash,
+    pub shuffle_stats: ShuffleStats,
+    // Doors grouped by the area they are in, sorted by name
+    pub areas: BTreeMap<String, Vec<SpoilerConnection>>,
+    // Doors that kept their vanilla entrance, either by default or because of the settings
+    pub excluded_doors: Vec<String>,
+    // Items in shuffled chests, sorted by chest
+    pub chests: Vec<SpoilerChest>,
+    // Abilities given by shuffled ability sources, sorted by node
+    pub ability_sources: Vec<SpoilerAbilitySource>,
+    // Enemies in shuffled enemy slots, sorted by slot
+    pub enemies: Vec<SpoilerEnemy>,
+    // The boss behind each door of a shuffled boss room, sorted by door
+    pub bosses: Vec<SpoilerBoss>,
+    // Tracks in shuffled music slots, sorted by slot
+    pub music: Vec<SpoilerTrack>,
+    // Colors of the recolored Kirbys, in the order of `Kirby::ALL`
+    pub kirby_colors: Vec<SpoilerKirbyColor>,
+    pub playthrough: Playthrough,
+}
+
+impl Spoiler {
+    pub fn new<N: Display>(
+        settings: Config,
+        hash: SeedHash,
+        shuffle_stats: ShuffleStats,
+        connections: Vec<Connection<N>>,
+        contents: ShuffledContents<N>,
+        playthrough: Playthrough,
+    ) -> Self {
+        let mut areas: BTreeMap<String, Vec<SpoilerConnection>> = BTreeMap::new();
+        for connection in connections {
+            let door = connection.door.to_string();
+            areas
+                .entry(area(&door).to_string())
+                .or_default()
+                .push(SpoilerConnection {
+                    door,
+                    entrance: connection.entrance.to_string(),
+                    placement: connection.placement,
+                });
+        }
+        for doors in areas.values_mut() {
+            doors.sort_by(|a, b| a.door.cmp(&b.door));
+        }
+
+        let mut excluded_doors: Vec<String> = areas
+            .values()
+            .flatten()
+            .filter(|connection| connection.placement == Placement::Excluded)
+            .map(|connection| connection.door.clone())
+            .collect();
+        excluded_doors.sort();
+
+        let mut chests: Vec<SpoilerChest> = contents
+            .chests
+            .iter()
+            .map(|chest| SpoilerChest {
+                chest: chest.name.clone(),
+                item: chest.item.name.clone(),
+            })
+            .collect();
+        chests.sort_by(|a, b| a.chest.cmp(&b.chest));
+
+        let mut ability_sources: Vec<SpoilerAbilitySource> = contents
+            .ability_sources
+            .iter()
+            .map(|source| SpoilerAbilitySource {
+                node: source.node.to_string(),
+                ability: source.ability,
+            })
+            .collect();
+        ability_sources.sort_by(|a, b| a.node.cmp(&b.node));
+
+        let mut enemies: Vec<SpoilerEnemy> = contents
+            .enemy_slots
+            .iter()
+            .map(|slot| SpoilerEnemy {
+                slot: slot.name.clone(),
+                enemy: slot.enemy.name.clone(),
+            })
+            .collect();
+        enemies.sort_by(|a, b| a.slot.cmp(&b.slot));
+
+        let mut bosses: Vec<SpoilerBoss> = contents
+            .boss_rooms
+            .iter()
+            .flat_map(|room| {
+                room.doors.iter().map(move |door| SpoilerBoss {
+                    door: door.to_string(),
+                    boss: room.boss.name.clone(),
+                })
+            })
+            .collect();
+        bosses.sort_by(|a, b| a.door.cmp(&b.door));
+
+        let mut music: Vec<SpoilerTrack> = contents
+            .music_slots
+            .iter()
+            .map(|slot| SpoilerTrack {
+                slot: slot.name.clone(),
+                track: slot.track.name.clone(),
+            })
+            .collect();
+        music.sort_by(|a, b| a.slot.cmp(&b.slot));
+
+        let mut kirby_colors: Vec<SpoilerKirbyColor> = vec![];
+        for palette in contents.kirby_palettes {
+            let kirby = palette.kirby.to_string();
+            if let Some(color) = palette.color {
+                if !kirby_colors.iter().any(|other| other.kirby == kirby) {
+                    kirby_colors.push(SpoilerKirbyColor {
+                        kirby,
+                        color: color.to_string(),
+                    });
+                }
+            }
+        }
+
+        Self {
+            settings_string: settings.to_settings_string(),
+            settings,
+            hash,
+            shuffle_stats,
+            areas,
+            excluded_doors,
+            chests,
+            ability_sources,
+            enemies,
+            bosses,
+            music,
+            kirby_colors,
+            playthrough,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl fmt::Display for Spoiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "KatAM Randomizer Spoiler Log")?;
+        writeln!(f)?;
+        writeln!(f, "Seed: {}", self.settings.seed)?;
+        writeln!(f, "Hash: {}", self.hash)?;
+        writeln!(f, "Settings: {}", self.settings_string)?;
+        writeln!(f, "Entrance shuffle: {:?}", self.settings.entrance_shuffle)?;
+        writeln!(f, "Ability shuffle: {:?}", self.settings.ability_shuffle)?;
+        writeln!(f, "Enemy shuffle: {:?}", self.settings.enemy_shuffle)?;
+        let boss_shuffle = if self.settings.shuffle_bosses {
+            "On"
+        } else {
+            "Off"
+        };
+        writeln!(f, "Boss shuffle: {}", boss_shuffle)?;
+        writeln!(f, "Music shuffle: {:?}", self.settings.music_shuffle)?;
+        let kirby_colors: Vec<String> = self
+            .settings
+            .kirby_colors
+            .iter()
+            .map(|color| color.to_string())
+            .collect();
+        writeln!(f, "Kirby colors: {}", kirby_colors.join(" / "))?;
+        writeln!(f, "Shuffle: {}", self.shuffle_stats)?;
+
+        if !self.settings.plando.connections.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Plando:")?;
+            for connection in &self.settings.plando.connections {
+                writeln!(f, "    {} -> {}", connection.door, connection.entrance)?;
+            }
+        }
+
+        if !self.excluded_doors.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Excluded doors:")?;
+            for door in &self.excluded_doors {
+                writeln!(f, "    {}", door)?;
+            }
+        }
+
+        for (area, connections) in &self.areas {
+            writeln!(f)?;
+            writeln!(f, "{}:", area)?;
+            for connection in connections {
+                write!(f, "    {} -> {}", connection.door, connection.entrance)?;
+                match connection.placement {
+                    Placement::Shuffled => writeln!(f)?,
+                    Placement::Pinned => writeln!(f, " (pinned)")?,
+                    Placement::Excluded => writeln!(f, " (excluded)")?,
+                    Placement::Boss => writeln!(f, " (boss)")?,
+                }
+            }
+        }
+
+        if !self.chests.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Chests:")?;
+            for chest in &self.chests {
+                writeln!(f, "    {}: {}", chest.chest, chest.item)?;
+            }
+        }
+
+        if !self.ability_sources.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Ability sources:")?;
+            for source in &self.ability_sources {
+                writeln!(f, "    {}: {:?}", source.node, source.ability)?;
+            }
+        }
+
+        if !self.enemies.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Enemies:")?;
+            for enemy in &self.enemies {
+                writeln!(f, "    {}: {}", enemy.slot, enemy.enemy)?;
+            }
+        }
+
+        if !self.bosses.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Bosses:")?;
+            for boss in &self.bosses {
+                writeln!(f, "    {}: {}", boss.door, boss.boss)?;
+            }
+        }
+
+        if !self.music.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Music:")?;
+            for track in &self.music {
+                writeln!(f, "    {}: {}", track.slot, track.track)?;
+            }
+        }
+
+        if !self.kirby_colors.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Kirby colors:")?;
+            for color in &self.kirby_colors {
+                writeln!(f, "    {}: {}", color.kirby, color.color)?;
+            }
+        }
+
+        writeln!(f)?;
+        write!(f, "{}", self.playthrough)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        boss::{Boss, BossKind},
+        config::{
+            AbilityShuffleType, EnemyShuffleType, MusicShuffleType, Plando, PlandoConnection,
+        },
+        enemy::{Enemy, EnemyClass},
+        item::Item,
+        music::Track,
+        palette::{Kirby, KirbyColor, Rgb, SprayPaint},
+        playthrough::{GoalPath, PlaythroughSphere},
+    };
+
+    fn connection(door: &str, entrance: &str, placement: Placement) -> Connection<String> {
+        Connection {
+            door: door.to_string(),
+            entrance: entrance.to_string(),
+            placement,
+        }
+    }
+
+    fn chest(name: &str, item: &str) -> Chest<String> {
+        Chest {
+            name: name.to_string(),
+            node: "RRoute: Door a".to_string(),
+            requires: None,
+            item: Item {
+                name: item.to_string(),
+                value: vec![0],
+            },
+            addresses: vec![],
+        }
+    }
+
+    fn test_spoiler() -> Spoiler {
+        let settings = Config {
+            seed: 42,
+            plando: Plando {
+                connections: vec![PlandoConnection {
+                    door: "PP: Door b".to_string(),
+                    entrance: "PP: Entrance c".to_string(),
+                }],
+            },
+            ability_shuffle: AbilityShuffleType::SameTier,
+            enemy_shuffle: EnemyShuffleType::Full,
+            shuffle_bosses: true,
+            music_shuffle: MusicShuffleType::ExcludeJingles,
+            kirby_colors: [
+                KirbyColor::Vanilla,
+                KirbyColor::Random,
+                KirbyColor::Preset(SprayPaint::Carbon),
+                KirbyColor::Vanilla,
+            ],
+            ..Config::default()
+        };
+        Spoiler::new(
+            settings,
+            SeedHash(vec!["Kirby", "Cutter", "Maxim Tomato", "Kirby", "UFO"]),
+            ShuffleStats {
+                attempts: 1,
+                ..ShuffleStats::default()
+            },
+            vec![
+                connection("RRoute: Door a", "PP: Entrance a", Placement::Shuffled),
+                connection("PP: Door b", "PP: Entrance c", Placement::Pinned),
+                connection("PP: Door a", "RRoute: Entrance b", Placement::Shuffled),
+                connection("CCav: Door c", "CCav: Entrance c", Placement::Excluded),
+                connection("CCav: Door d", "PP: Entrance d", Placement::Boss),
+            ],
+            ShuffledContents {
+                chests: &[
+                    chest("RRoute: Chest b", "Vitality Heart"),
+                    chest("PP: Chest a", "Spray Paint"),
+                ],
+                ability_sources: &[
+                    AbilitySource {
+                        node: "RRoute: Door a".to_string(),
+                        ability: Ability::Ufo,
+                        addresses: vec![],
+                    },
+                    AbilitySource {
+                        node: "PP: Door a".to_string(),
+                        ability: Ability::Burning,
+                        addresses: vec![],
+                    },
+                ],
+                enemy_slots: &[EnemySlot {
+                    name: "PP: Enemy in room a".to_string(),
+                    node: "PP: Door a".to_string(),
+                    enemy: Enemy {
+                        name: "Waddle Dee".to_string(),
+                        value: vec![0],
+                        class: EnemyClass::Ground,
+                    },
+                    addresses: vec![],
+                }],
+                boss_rooms: &[BossRoom {
+                    doors: vec!["PP: Door a".to_string()],
+                    boss: Boss {
+                        name: "Wiz".to_string(),
+                        kind: BossKind::Boss,
+                        entrance: "RRoute: Entrance b".to_string(),
+                    },
+                }],
+                music_slots: &[MusicSlot {
+                    name: "PP: Main theme".to_string(),
+                    track: Track {
+                        name: "Rainbow Route".to_string(),
+                        value: vec![0],
+                        jingle: false,
+                    },
+                    addresses: vec![],
+                }],
+                kirby_palettes: &[
+                    KirbyPalette {
+                        kirby: Kirby::Pink,
+                        addresses: vec![],
+                        color: None,
+                    },
+                    KirbyPalette {
+                        kirby: Kirby::Yellow,
+                        addresses: vec![],
+                        color: Some(Rgb::new(0x12, 0x34, 0x56)),
+                    },
+                ],
+            },
+            Playthrough {
+                spheres: vec![
+                    PlaythroughSphere {
+                        abilities: vec![Ability::Cutter],
+                        nodes: vec!["RRoute: Door a".to_string(), "PP: Entrance a".to_string()],
+                    },
+                    PlaythroughSphere {
+                        abilities: vec![],
+                        nodes: vec!["PP: Door a".to_string()],
+                    },
+                ],
+                goal_paths: vec![
+                    GoalPath {
+                        goal: "PP: Door a".to_string(),
+                        path: Some(vec![
+                            "RRoute: Door a".to_string(),
+                            "PP: Entrance a".to_string(),
+                            "PP: Door a".to_string(),
+                        ]),
+                    },
+                    GoalPath {
+                        goal: "CCav: Door c".to_string(),
+                        path: None,
+                    },
+                ],
+            },
+        )
+    }
+
+    #[test]
+    fn test_spoiler_text() {
+        assert_eq!(
+            test_spoiler().to_string(),
+            "KatAM Randomizer Spoiler Log
+
+Seed: 42
+Hash: Kirby / Cutter / Maxim Tomato / Kirby / UFO
+Settings: CCoAAQpQUDogRG9vciBiDlBQOiBFbnRyYW5jZSBjAAAAAQIBAQABAgUA
+Entrance shuffle: Standard
+Ability shuffle: SameTier
+Enemy shuffle: Full
+Boss shuffle: On
+Music shuffle: ExcludeJingles
+Kirby colors: Vanilla / Random / Carbon / Vanilla
+Shuffle: 1 shuffle attempt(s)
+
+Plando:
+    PP: Door b -> PP: Entrance c
+
+Excluded doors:
+    CCav: Door c
+
+CCav:
+    CCav: Door c -> CCav: Entrance c (excluded)
+    CCav: Door d -> PP: Entrance d (boss)
+
+PP:
+    PP: Door a -> RRoute: Entrance b
+    PP: Door b -> PP: Entrance c (pinned)
+
+RRoute:
+    RRoute: Door a -> PP: Entrance a
+
+Chests:
+    PP: Chest a: Spray Paint
+    RRoute: Chest b: Vitality Heart
+
+Ability sources:
+    PP: Door a: Burning
+    RRoute: Door a: Ufo
+
+Enemies:
+    PP: Enemy in room a: Waddle Dee
+
+Bosses:
+    PP: Door a: Wiz
+
+Music:
+    PP: Main theme: Rainbow Route
+
+Kirby colors:
+    Yellow Kirby: #123456
+
+Playthrough:
+    Sphere 0:
+        Abilities: Cutter
+        RRoute: Door a
+        PP: Entrance a
+    Sphere 1:
+        PP: Door a
+
+Paths to goals:
+    PP: Door a:
+        RRoute: Door a
+        PP: Entrance a
+        PP: Door a
+    CCav: Door c:
+        Unreachable
+"
+        );
+    }
+
+    #[test]
+    fn test_spoiler_json() -> serde_json::Result<()> {
+        let json: serde_json::Value = serde_json::from_str(&test_spoiler().to_json()?)?;
+        assert_eq!(json["settings"]["seed"], 42);
+        assert_eq!(json["hash"][2], "Maxim Tomato");
+        assert_eq!(json["settings"]["entrance_shuffle"], "Standard");
+        assert_eq!(json["areas"]["PP"][1]["door"], "PP: Door b");
+        assert_eq!(json["areas"]["PP"][1]["placement"], "pinned");
+        assert_eq!(json["excluded_doors"][0], "CCav: Door c");
+        assert_eq!(json["chests"][0]["chest"], "PP: Chest a");
+        assert_eq!(json["chests"][0]["item"], "Spray Paint");
+        assert_eq!(json["ability_sources"][0]["node"], "PP: Door a");
+        assert_eq!(json["ability_sources"][0]["ability"], "Burning");
+        assert_eq!(json["enemies"][0]["enemy"], "Waddle Dee");
+        assert_eq!(json["bosses"][0]["boss"], "Wiz");
+        assert_eq!(json["music"][0]["track"], "Rainbow Route");
+        assert_eq!(json["kirby_colors"][0]["color"], "#123456");
+        assert_eq!(json["playthrough"]["spheres"][0]["abilities"][0], "Cutter");
+        assert_eq!(
+            json["playthrough"]["goal_paths"][1]["path"],
+            serde_json::Value::Null
+        );
+        Ok(())
+    }
+}